@@ -0,0 +1,442 @@
+/*
+ *  backend.rs
+ *
+ *  Copyright (c) 2024-2024 Cédric ROYER <cedric dot royer at zaclys dot net>
+ *
+ *  This program is free software; you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation; either version 2 of the License, or
+ *  (at your option) any later version.
+ *
+ *  This program is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+pub mod flatpak;
+pub mod pacman;
+
+use crate::engine::compute_actions::{self, Actions, Package, PackageManager, PackageOrGroup};
+use crate::engine::effects::{Effects, Simulation};
+use crate::engine::plan::Plan;
+use crate::engine::reference::Reference;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::process::{Command, ExitStatus};
+use std::str::Utf8Error;
+use std::{io, result, str};
+
+#[derive(Debug)]
+pub enum BackendError {
+    ParseError(String),
+    Utf8(str::Utf8Error),
+    Io(io::Error),
+    CommandFailed(String, ExitStatus),
+}
+
+pub type Result<T> = result::Result<T, BackendError>;
+
+/// A package manager able to list, install and remove packages.
+pub trait Backend {
+    /// Whether the packages declared with this manager are handled by this backend.
+    fn manages(&self, manager: &PackageManager) -> bool;
+
+    /// Installed packages, explicitly or as dependencies.
+    fn get_installed_packages(&self) -> Result<HashSet<Package>>;
+
+    /// Members of every group known by the package manager, installed or not.
+    /// The declared groups are expanded to their members, empty for the managers without groups.
+    fn get_group_members(&self) -> Result<HashMap<String, HashSet<String>>> {
+        Ok(HashMap::new())
+    }
+
+    /// Packages held back from upgrades, empty for the managers without hold list.
+    fn get_held_packages(&self) -> Result<HashSet<Package>> {
+        Ok(HashSet::new())
+    }
+
+    /// Commands installing the given packages, empty if there is nothing to do.
+    /// Excluded packages must not be installed along with a group.
+    fn install_commands(&self, to_add: &[&PackageOrGroup], excluded: &[&PackageOrGroup]) -> Vec<Command>;
+
+    /// Commands changing the install reason of the given packages to their declared one.
+    fn mark_commands(&self, _to_mark: &[&PackageOrGroup]) -> Vec<Command> {
+        Vec::new()
+    }
+
+    /// Commands removing the given packages, empty if there is nothing to do.
+    fn remove_commands(&self, to_delete: &[&PackageOrGroup]) -> Vec<Command>;
+
+    /// Commands installing a version satisfying the constraint of the given packages,
+    /// empty when the constraints are only reported.
+    fn version_commands(&self, _unsatisfied: &[&PackageOrGroup]) -> Vec<Command> {
+        Vec::new()
+    }
+
+    /// Commands replacing the hold list by the given packages.
+    fn hold_commands(&self, _held: &[&PackageOrGroup]) -> Vec<Command> {
+        Vec::new()
+    }
+
+    /// Held packages for which a newer version is available.
+    fn held_updates(&self, _held: &[&PackageOrGroup]) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+
+    /// Packages removed along with the deleted ones, chosen by the package manager:
+    /// the dependencies of a recursive removal and the orphans swept afterwards.
+    fn removed_along(&self, _to_delete: &[&PackageOrGroup]) -> Result<HashSet<PackageOrGroup>> {
+        Ok(HashSet::new())
+    }
+
+    /// Packages to install at a given version for which no package file is available:
+    /// the package manager would install the latest version instead.
+    fn missing_archives(&self, _to_add: &[&PackageOrGroup]) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Problems with the declared packages, found before running any command.
+    fn validate(&self, _reference: &[&PackageOrGroup]) -> Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+
+    /// Dependencies pulled in or orphaned by installing and removing the given packages.
+    fn effects(&self, _to_add: &[&PackageOrGroup], _to_delete: &[&PackageOrGroup]) -> Result<Effects> {
+        Ok(Effects::default())
+    }
+
+    /// Packages installed and removed by the actions, found without changing the system.
+    /// By default, the declared names are reported as is.
+    fn simulate(&self, to_add: &[&PackageOrGroup], to_delete: &[&PackageOrGroup]) -> Result<Simulation> {
+        Ok(Simulation {
+            installed: to_add.iter().map(|p| format!("{} ({})", p.name, p.manager)).collect(),
+            removed: to_delete.iter().map(|p| format!("{} ({})", p.name, p.manager)).collect(),
+            notes: Vec::new(),
+        })
+    }
+
+    /// Commands removing the packages left unused, run after the actions.
+    fn sweep_commands(&self) -> Result<Vec<Command>> {
+        Ok(Vec::new())
+    }
+
+    /// All commands needed to apply the actions handled by this backend.
+    fn commands(&self, actions: &Actions) -> Vec<Command> {
+        let (to_add, to_delete) = self.managed(actions);
+        let mut commands = Vec::new();
+        if !to_add.is_empty() {
+            let excluded: Vec<&PackageOrGroup> = actions.excluded.iter().filter(|p| self.manages(&p.manager)).collect();
+            commands.extend(self.install_commands(&to_add, &excluded));
+        }
+        let to_mark: Vec<&PackageOrGroup> = actions.to_mark.iter().filter(|p| self.manages(&p.manager)).collect();
+        if !to_mark.is_empty() {
+            commands.extend(self.mark_commands(&to_mark));
+        }
+        if !to_delete.is_empty() {
+            commands.extend(self.remove_commands(&to_delete));
+        }
+        let unsatisfied: Vec<&PackageOrGroup> = actions.unsatisfied.keys().filter(|p| self.manages(&p.manager)).collect();
+        if !unsatisfied.is_empty() {
+            commands.extend(self.version_commands(&unsatisfied));
+        }
+        // held packages are installed before being held
+        commands.extend(self.hold_change_commands(actions));
+        commands
+    }
+
+    /// Commands replacing the hold list, when a package handled by this backend is held or released.
+    fn hold_change_commands(&self, actions: &Actions) -> Vec<Command> {
+        let hold_changed = actions.to_hold.iter().chain(actions.to_release.iter()).any(|p| self.manages(&p.manager));
+        if !hold_changed {
+            return Vec::new();
+        }
+        let held: Vec<&PackageOrGroup> = actions.held.iter().filter(|p| self.manages(&p.manager)).collect();
+        self.hold_commands(&held)
+    }
+
+    /// Packages to add and to delete handled by this backend, the packages installed from another
+    /// repository than the declared one are installed again.
+    fn managed<'a>(&self, actions: &'a Actions) -> (Vec<&'a PackageOrGroup>, Vec<&'a PackageOrGroup>) {
+        let to_add = actions.to_add.iter().chain(actions.drifted.keys()).filter(|p| self.manages(&p.manager)).collect();
+        let to_delete = actions.to_delete.iter().filter(|p| self.manages(&p.manager)).collect();
+        (to_add, to_delete)
+    }
+}
+
+/// Compare the reference with the packages installed, and held, by each backend.
+pub fn compute_actions(
+    backends: &[Box<dyn Backend>],
+    reference: &HashSet<PackageOrGroup>,
+    excluded: &HashSet<PackageOrGroup>,
+    held: &HashSet<PackageOrGroup>,
+) -> Result<Actions> {
+    let mut actions = Actions::default();
+    for backend in backends {
+        let backend_reference = HashSet::from_iter(reference.iter().filter(|p| backend.manages(&p.manager)).cloned());
+        let backend_excluded = HashSet::from_iter(excluded.iter().filter(|p| backend.manages(&p.manager)).cloned());
+        let backend_held = HashSet::from_iter(held.iter().filter(|p| backend.manages(&p.manager)).cloned());
+        let current = backend.get_installed_packages()?;
+        let groups = backend.get_group_members()?;
+        actions.extend(compute_actions::compute_actions(backend_reference, current, backend_excluded, &groups));
+        actions.extend(compute_actions::compute_holds(backend_held, backend.get_held_packages()?));
+    }
+    Ok(actions)
+}
+
+/// Held packages missing updates, for all backends.
+pub fn held_updates(backends: &[Box<dyn Backend>], held: &HashSet<PackageOrGroup>) -> Result<Vec<String>> {
+    let mut updates = Vec::new();
+    for backend in backends {
+        let backend_held: Vec<&PackageOrGroup> = held.iter().filter(|p| backend.manages(&p.manager)).collect();
+        if !backend_held.is_empty() {
+            updates.extend(backend.held_updates(&backend_held)?);
+        }
+    }
+    updates.sort();
+    Ok(updates)
+}
+
+/// Packages removed along with the deleted ones, for all backends.
+pub fn removed_along(backends: &[Box<dyn Backend>], actions: &Actions) -> Result<Vec<PackageOrGroup>> {
+    let mut removed = Vec::new();
+    for backend in backends {
+        let (_, to_delete) = backend.managed(actions);
+        removed.extend(backend.removed_along(&to_delete)?);
+    }
+    removed.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(removed)
+}
+
+/// Packages to install at a given version without package file, for all backends.
+pub fn missing_archives(backends: &[Box<dyn Backend>], to_add: &HashSet<PackageOrGroup>) -> Vec<String> {
+    let mut missing = Vec::new();
+    for backend in backends {
+        let backend_to_add: Vec<&PackageOrGroup> = to_add.iter().filter(|p| backend.manages(&p.manager) && p.version.is_some()).collect();
+        if !backend_to_add.is_empty() {
+            missing.extend(backend.missing_archives(&backend_to_add));
+        }
+    }
+    missing.sort();
+    missing
+}
+
+/// Installed version of every package whose version is known, for all backends.
+pub fn installed_versions(backends: &[Box<dyn Backend>]) -> Result<HashMap<String, String>> {
+    let mut versions = HashMap::new();
+    for backend in backends {
+        versions.extend(backend.get_installed_packages()?.into_iter().filter_map(|p| Some((p.name, p.version?))));
+    }
+    Ok(versions)
+}
+
+/// Number of explicitly installed packages, for all backends.
+pub fn count_explicit(backends: &[Box<dyn Backend>]) -> Result<usize> {
+    let mut count = 0;
+    for backend in backends {
+        count += backend.get_installed_packages()?.iter().filter(|p| p.reason == compute_actions::InstallReason::Explicit).count();
+    }
+    Ok(count)
+}
+
+/// Check that every declared package is known by its backend.
+pub fn validate(backends: &[Box<dyn Backend>], reference: &HashSet<PackageOrGroup>) -> Result<Vec<String>> {
+    let mut problems = Vec::new();
+    for backend in backends {
+        let declared: Vec<&PackageOrGroup> = reference.iter().filter(|p| backend.manages(&p.manager)).collect();
+        problems.extend(backend.validate(&declared)?);
+    }
+    problems.sort();
+    Ok(problems)
+}
+
+/// Transitive effects of the actions, for all backends.
+pub fn compute_effects(backends: &[Box<dyn Backend>], actions: &Actions) -> Result<Effects> {
+    let mut effects = Effects::default();
+    for backend in backends {
+        let (to_add, to_delete) = backend.managed(actions);
+        if !to_add.is_empty() || !to_delete.is_empty() {
+            effects.extend(backend.effects(&to_add, &to_delete)?);
+        }
+    }
+    Ok(effects)
+}
+
+/// Simulate the transaction of every backend, without root privileges.
+pub fn simulate(backends: &[Box<dyn Backend>], actions: &Actions) -> Result<Simulation> {
+    let mut simulation = Simulation::default();
+    for backend in backends {
+        let (to_add, to_delete) = backend.managed(actions);
+        if !to_add.is_empty() || !to_delete.is_empty() {
+            simulation.extend(backend.simulate(&to_add, &to_delete)?);
+        }
+    }
+    Ok(simulation)
+}
+
+/// Machine-readable description of the actions, with the commands of every backend.
+pub fn plan(backends: &[Box<dyn Backend>], actions: &Actions, reference: &Reference) -> Plan {
+    let commands: Vec<Command> = backends.iter().flat_map(|backend| backend.commands(actions)).collect();
+    let hold_commands: Vec<Command> = backends.iter().flat_map(|backend| backend.hold_change_commands(actions)).collect();
+    Plan::new(actions, &commands, &hold_commands, reference)
+}
+
+pub fn print_actions(backends: &[Box<dyn Backend>], actions: &Actions) {
+    for backend in backends {
+        for command in backend.commands(actions) {
+            println!("{:?}", command);
+        }
+    }
+}
+
+pub fn apply_actions(backends: &[Box<dyn Backend>], actions: &Actions) -> Result<()> {
+    for backend in backends {
+        for command in backend.commands(actions) {
+            run(command)?;
+        }
+    }
+    Ok(())
+}
+
+/// Print the commands removing the unused packages, they depend on the current state of the system.
+pub fn print_sweep(backends: &[Box<dyn Backend>]) -> Result<()> {
+    for backend in backends {
+        for command in backend.sweep_commands()? {
+            println!("{:?}", command);
+        }
+    }
+    Ok(())
+}
+
+/// Remove the unused packages, to be called once the actions are applied.
+pub fn sweep(backends: &[Box<dyn Backend>]) -> Result<()> {
+    for backend in backends {
+        for command in backend.sweep_commands()? {
+            run(command)?;
+        }
+    }
+    Ok(())
+}
+
+fn run(mut command: Command) -> Result<()> {
+    let status = command.status()?;
+    if !status.success() {
+        return Err(BackendError::CommandFailed(format!("{:?} failed", command), status));
+    }
+    Ok(())
+}
+
+impl From<Utf8Error> for BackendError {
+    fn from(err: Utf8Error) -> BackendError {
+        BackendError::Utf8(err)
+    }
+}
+
+impl From<io::Error> for BackendError {
+    fn from(err: io::Error) -> BackendError {
+        BackendError::Io(err)
+    }
+}
+
+impl Display for BackendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BackendError::ParseError(err) => write!(f, "parsing error: {err}"),
+            BackendError::Utf8(err) => write!(f, "cannot parse Utf8: {err}"),
+            BackendError::Io(err) => write!(f, "cannot run package manager: {err}"),
+            BackendError::CommandFailed(message, status) => write!(f, "{message} with status {status}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fake;
+
+    impl Backend for Fake {
+        fn manages(&self, manager: &PackageManager) -> bool {
+            *manager == PackageManager::PACMAN
+        }
+
+        fn get_installed_packages(&self) -> Result<HashSet<Package>> {
+            Ok(HashSet::from([
+                Package::new("to_keep".to_string(), Vec::new(), PackageManager::PACMAN),
+                Package::new("to_rm".to_string(), Vec::new(), PackageManager::PACMAN),
+            ]))
+        }
+
+        fn install_commands(&self, to_add: &[&PackageOrGroup], _excluded: &[&PackageOrGroup]) -> Vec<Command> {
+            let mut cmd = Command::new("install");
+            cmd.args(to_add.iter().map(|p| &p.name));
+            vec![cmd]
+        }
+
+        fn remove_commands(&self, to_delete: &[&PackageOrGroup]) -> Vec<Command> {
+            let mut cmd = Command::new("remove");
+            cmd.args(to_delete.iter().map(|p| &p.name));
+            vec![cmd]
+        }
+    }
+
+    #[test]
+    fn compute_actions_only_for_managed_packages() {
+        // Given
+        let backends: Vec<Box<dyn Backend>> = vec![Box::new(Fake)];
+        let mut reference = HashSet::new();
+        reference.insert(PackageOrGroup::new("to_keep".to_string(), PackageManager::PACMAN));
+        reference.insert(PackageOrGroup::new("to_add".to_string(), PackageManager::PACMAN));
+        reference.insert(PackageOrGroup::new("unmanaged".to_string(), PackageManager::LOCAL));
+
+        // When
+        let actions = compute_actions(&backends, &reference, &HashSet::new(), &HashSet::new()).unwrap();
+
+        // Then
+        assert_eq!(actions, Actions {
+            to_add: HashSet::from([PackageOrGroup::new("to_add".to_string(), PackageManager::PACMAN)]),
+            to_delete: HashSet::from([PackageOrGroup::new("to_rm".to_string(), PackageManager::PACMAN)]),
+            ..Default::default()
+        });
+    }
+
+    #[test]
+    fn commands_skip_empty_actions() {
+        // Given
+        let actions = Actions {
+            to_add: HashSet::from([PackageOrGroup::new("to_add".to_string(), PackageManager::PACMAN)]),
+            to_delete: HashSet::from([PackageOrGroup::new("unmanaged".to_string(), PackageManager::LOCAL)]),
+            ..Default::default()
+        };
+
+        // When
+        let commands = Fake.commands(&actions);
+
+        // Then
+        assert_eq!(commands.len(), 1);
+        assert_eq!(format!("{:?}", commands[0]), "\"install\" \"to_add\"");
+    }
+
+    #[test]
+    fn simulate_reports_declared_names_by_default() {
+        // Given
+        let backends: Vec<Box<dyn Backend>> = vec![Box::new(Fake)];
+        let actions = Actions {
+            to_add: HashSet::from([PackageOrGroup::new("to_add".to_string(), PackageManager::PACMAN)]),
+            to_delete: HashSet::from([PackageOrGroup::new("unmanaged".to_string(), PackageManager::LOCAL)]),
+            ..Default::default()
+        };
+
+        // When
+        let simulation = simulate(&backends, &actions).unwrap();
+
+        // Then
+        assert_eq!(simulation, Simulation {
+            installed: vec!["to_add (pacman)".to_string()],
+            ..Default::default()
+        });
+    }
+}
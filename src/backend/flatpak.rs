@@ -0,0 +1,123 @@
+/*
+ *  backend/flatpak.rs
+ *
+ *  Copyright (c) 2024-2024 Cédric ROYER <cedric dot royer at zaclys dot net>
+ *
+ *  This program is free software; you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation; either version 2 of the License, or
+ *  (at your option) any later version.
+ *
+ *  This program is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use std::collections::{BTreeMap, HashSet};
+use std::str;
+use std::process::Command;
+use crate::backend::{Backend, BackendError, Result};
+use crate::engine::compute_actions::{Package, PackageManager, PackageOrGroup};
+
+/// Flatpak applications, declared with `flatpak/` and optionally prefixed by their remote.
+pub struct Flatpak;
+
+impl Backend for Flatpak {
+    fn manages(&self, manager: &PackageManager) -> bool {
+        *manager == PackageManager::FLATPAK
+    }
+
+    fn get_installed_packages(&self) -> Result<HashSet<Package>> {
+        let output = match Command::new("flatpak").args(["list", "--app", "--columns=application,origin"]).output() {
+            Ok(output) => output,
+            // flatpak is optional, nothing is installed without it
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(HashSet::new()),
+            Err(err) => return Err(err.into()),
+        };
+        parse_flatpak_list(output.stdout)
+    }
+
+    fn install_commands(&self, to_add: &[&PackageOrGroup], _excluded: &[&PackageOrGroup]) -> Vec<Command> {
+        // one command per remote, applications without remote let flatpak find it
+        let mut by_remote: BTreeMap<Option<&String>, Vec<&String>> = BTreeMap::new();
+        for application in to_add {
+            by_remote.entry(application.repository.as_ref()).or_default().push(&application.name);
+        }
+        by_remote
+            .into_iter()
+            .map(|(remote, applications)| {
+                let mut cmd = Command::new("flatpak");
+                cmd.arg("install");
+                cmd.arg("--noninteractive");
+                cmd.args(remote);
+                cmd.args(applications);
+                cmd
+            })
+            .collect()
+    }
+
+    fn remove_commands(&self, to_delete: &[&PackageOrGroup]) -> Vec<Command> {
+        let mut cmd = Command::new("flatpak");
+        cmd.arg("uninstall");
+        cmd.arg("--noninteractive");
+        cmd.args(to_delete.iter().map(|application| &application.name));
+        vec![cmd]
+    }
+}
+
+fn parse_flatpak_list(output: Vec<u8>) -> Result<HashSet<Package>> {
+    let utf8_output = String::from(str::from_utf8(&output)?);
+    let mut applications = HashSet::new();
+    for row in utf8_output.split('\n').filter(|row| !row.is_empty()) {
+        let values: Vec<&str> = row.split('\t').collect();
+        if values.len() != 2 {
+            return Err(BackendError::ParseError(format!("cannot read flatpak application: {}", row)));
+        }
+        applications.insert(Package::new(values[0].to_string(), Vec::new(), PackageManager::FLATPAK));
+    }
+    Ok(applications)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+    use super::*;
+
+    #[test]
+    fn parse_list_nominal() {
+        // Given
+        let output = fs::read(Path::new("tests/data/flatpak_list_output"));
+        let mut expected = HashSet::<Package>::new();
+        expected.insert(Package::new("org.mozilla.firefox".to_string(), Vec::new(), PackageManager::FLATPAK));
+        expected.insert(Package::new("org.gimp.GIMP".to_string(), Vec::new(), PackageManager::FLATPAK));
+        expected.insert(Package::new("com.valvesoftware.Steam".to_string(), Vec::new(), PackageManager::FLATPAK));
+
+        // When
+        let applications = parse_flatpak_list(output.unwrap()).unwrap();
+
+        // Then
+        assert_eq!(applications, expected)
+    }
+
+    #[test]
+    fn install_commands_by_remote() {
+        // Given
+        let firefox = PackageOrGroup::new("org.mozilla.firefox".to_string(), PackageManager::FLATPAK);
+        let gimp = PackageOrGroup::new("org.gimp.GIMP".to_string(), PackageManager::FLATPAK).with_repository("flathub".to_string());
+
+        // When
+        let commands = Flatpak.install_commands(&[&firefox, &gimp], &[]);
+
+        // Then
+        let commands: Vec<String> = commands.iter().map(|cmd| format!("{:?}", cmd)).collect();
+        assert_eq!(commands, vec![
+            "\"flatpak\" \"install\" \"--noninteractive\" \"org.mozilla.firefox\"",
+            "\"flatpak\" \"install\" \"--noninteractive\" \"flathub\" \"org.gimp.GIMP\"",
+        ]);
+    }
+}
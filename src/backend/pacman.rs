@@ -0,0 +1,971 @@
+/*
+ *  backend/pacman.rs
+ *
+ *  Copyright (c) 2024-2024 Cédric ROYER <cedric dot royer at zaclys dot net>
+ *
+ *  This program is free software; you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation; either version 2 of the License, or
+ *  (at your option) any later version.
+ *
+ *  This program is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+pub mod conf;
+pub mod database;
+
+use std::cell::OnceCell;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::{env, str};
+use std::process::Command;
+use clap::ValueEnum;
+use crate::backend::{Backend, BackendError, Result};
+use crate::engine::compute_actions::{InstallReason, Package, PackageManager, PackageOrGroup};
+use crate::engine::effects::{Effects, Simulation};
+use crate::engine::version::{self, Constraint};
+use database::Resolution;
+
+pub const DEFAULT_DB_PATH: &str = "/var/lib/pacman";
+pub const DEFAULT_CACHE_DIR: &str = "/var/cache/pacman/pkg";
+
+/// How packages are removed.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, ValueEnum)]
+pub enum RemovalStrategy {
+    /// `pacman -R`: only the packages, their dependencies are left behind
+    Remove,
+    /// `pacman -Rs`: the packages and their dependencies not required by other packages
+    Recursive,
+    /// `pacman -Rns`: like recursive, without keeping backup of the configuration files
+    RecursiveNosave,
+}
+
+/// Packages from the sync repositories, foreign packages built from the AUR and
+/// foreign packages declared with `local/`.
+pub struct Pacman {
+    /// AUR helper used to build and install `aur/` packages (paru, yay...)
+    pub aur_helper: String,
+    pub removal: RemovalStrategy,
+    /// remove every orphan after the actions, not only the ones left by pacsync
+    pub sweep_orphans: bool,
+    /// pacman database directory, `/var/lib/pacman` by default
+    pub db_path: PathBuf,
+    /// directories searched for package files satisfying the version constraints,
+    /// the constraints are only reported when empty
+    pub archives: Vec<PathBuf>,
+    /// configuration file holding the `IgnorePkg` line managed by pacsync
+    pub pacman_conf: PathBuf,
+    /// sync databases, read once
+    sync: OnceCell<database::SyncDatabase>,
+}
+
+impl Pacman {
+    pub fn new(aur_helper: String) -> Self {
+        Pacman{aur_helper, removal: RemovalStrategy::Remove, sweep_orphans: false, db_path: PathBuf::from(DEFAULT_DB_PATH), archives: Vec::new(),
+            pacman_conf: PathBuf::from(conf::DEFAULT_PACMAN_CONF), sync: OnceCell::new()}
+    }
+
+    pub fn with_db_path(mut self, db_path: PathBuf) -> Self {
+        self.db_path = db_path;
+        self
+    }
+
+    pub fn with_removal(mut self, removal: RemovalStrategy) -> Self {
+        self.removal = removal;
+        self
+    }
+
+    pub fn with_orphan_sweep(mut self, sweep_orphans: bool) -> Self {
+        self.sweep_orphans = sweep_orphans;
+        self
+    }
+
+    pub fn with_archives(mut self, archives: Vec<PathBuf>) -> Self {
+        self.archives = archives;
+        self
+    }
+
+    pub fn with_pacman_conf(mut self, pacman_conf: PathBuf) -> Self {
+        self.pacman_conf = pacman_conf;
+        self
+    }
+
+    /// Sync databases, read on first use: they do not change during a run.
+    fn sync_database(&self) -> Result<&database::SyncDatabase> {
+        if let Some(sync) = self.sync.get() {
+            return Ok(sync);
+        }
+        let sync = database::SyncDatabase::read(&self.db_path)?;
+        Ok(self.sync.get_or_init(|| sync))
+    }
+
+    /// The AUR helper refuses to run as root: go back to the invoking user when run with sudo.
+    fn build_aur_command(&self) -> Command {
+        match env::var("SUDO_USER") {
+            Ok(user) => {
+                let mut cmd = Command::new("sudo");
+                cmd.arg("-u");
+                cmd.arg(user);
+                cmd.arg(&self.aur_helper);
+                cmd
+            }
+            Err(_) => Command::new(&self.aur_helper),
+        }
+    }
+}
+
+impl Backend for Pacman {
+    fn manages(&self, manager: &PackageManager) -> bool {
+        matches!(manager, PackageManager::PACMAN | PackageManager::LOCAL | PackageManager::AUR)
+    }
+
+    fn get_installed_packages(&self) -> Result<HashSet<Package>> {
+        Ok(installed_packages(database::read_local(&self.db_path)?, self.sync_database()?))
+    }
+
+    fn get_group_members(&self) -> Result<HashMap<String, HashSet<String>>> {
+        Ok(self.sync_database()?.groups())
+    }
+
+    fn get_held_packages(&self) -> Result<HashSet<Package>> {
+        Ok(conf::read_managed_ignored(&self.pacman_conf)?
+            .into_iter()
+            .map(|name| Package::new(name, Vec::new(), PackageManager::PACMAN))
+            .collect())
+    }
+
+    fn install_commands(&self, to_add: &[&PackageOrGroup], excluded: &[&PackageOrGroup]) -> Vec<Command> {
+        // local packages are only protected from removal, they are never installed
+        let names_for = |manager: PackageManager, reason: InstallReason| -> Vec<String> {
+            to_add.iter().filter(|&p_or_g| p_or_g.manager == manager && p_or_g.reason == reason).map(|p_or_g| target(p_or_g)).collect()
+        };
+        // excluded members of the groups to install
+        let mut ignored: Vec<&str> = excluded.iter().filter(|&p| p.manager == PackageManager::PACMAN).map(|p| p.name.as_str()).collect();
+        ignored.sort();
+        let mut commands = Vec::new();
+        for reason in [InstallReason::Explicit, InstallReason::Dependency] {
+            // packages with a version constraint are installed from the archives when a package file matches
+            let files: Vec<PathBuf> = to_add
+                .iter()
+                .filter(|&p_or_g| p_or_g.manager == PackageManager::PACMAN && p_or_g.reason == reason)
+                .filter_map(|p_or_g| self.archive_for(p_or_g))
+                .collect();
+            if !files.is_empty() {
+                let mut cmd = Command::new("sudo");
+                cmd.arg("pacman");
+                cmd.arg("-U");
+                if reason == InstallReason::Dependency {
+                    cmd.arg("--asdeps");
+                }
+                cmd.args(files);
+                commands.push(cmd);
+            }
+            let names: Vec<String> = to_add
+                .iter()
+                .filter(|&p_or_g| p_or_g.manager == PackageManager::PACMAN && p_or_g.reason == reason)
+                .filter(|&p_or_g| self.archive_for(p_or_g).is_none())
+                .map(|p_or_g| target(p_or_g))
+                .collect();
+            if !names.is_empty() {
+                let mut cmd = Command::new("sudo");
+                cmd.arg("pacman");
+                cmd.arg("-S");
+                if reason == InstallReason::Dependency {
+                    cmd.arg("--asdeps");
+                }
+                cmd.args(names);
+                if !ignored.is_empty() {
+                    cmd.arg("--ignore");
+                    cmd.arg(ignored.join(","));
+                }
+                commands.push(cmd);
+            }
+        }
+        for reason in [InstallReason::Explicit, InstallReason::Dependency] {
+            let names = names_for(PackageManager::AUR, reason);
+            if !names.is_empty() {
+                let mut cmd = self.build_aur_command();
+                cmd.arg("-S");
+                cmd.arg("--aur");
+                if reason == InstallReason::Dependency {
+                    cmd.arg("--asdeps");
+                }
+                cmd.args(names);
+                commands.push(cmd);
+            }
+        }
+        commands
+    }
+
+    fn mark_commands(&self, to_mark: &[&PackageOrGroup]) -> Vec<Command> {
+        let mut commands = Vec::new();
+        for (reason, option) in [(InstallReason::Dependency, "--asdeps"), (InstallReason::Explicit, "--asexplicit")] {
+            let mut names: Vec<&String> = to_mark.iter().filter(|&p_or_g| p_or_g.reason == reason).map(|p_or_g| &p_or_g.name).collect();
+            if names.is_empty() {
+                continue;
+            }
+            names.sort();
+            let mut cmd = Command::new("sudo");
+            cmd.arg("pacman");
+            cmd.arg("-D");
+            cmd.arg(option);
+            cmd.args(names);
+            commands.push(cmd);
+        }
+        commands
+    }
+
+    fn remove_commands(&self, to_delete: &[&PackageOrGroup]) -> Vec<Command> {
+        let mut cmd = Command::new("sudo");
+        cmd.arg("pacman");
+        cmd.arg(self.removal.operation());
+        cmd.args(to_delete.iter().map(|p_or_g| &p_or_g.name));
+        vec![cmd]
+    }
+
+    fn version_commands(&self, unsatisfied: &[&PackageOrGroup]) -> Vec<Command> {
+        let files: Vec<PathBuf> = unsatisfied
+            .iter()
+            .filter(|&p_or_g| p_or_g.manager == PackageManager::PACMAN)
+            .filter_map(|p_or_g| find_archive(&self.archives, &p_or_g.name, &Constraint::parse(p_or_g.version.as_ref()?).ok()?))
+            .collect();
+        if files.is_empty() {
+            return Vec::new();
+        }
+        let mut cmd = Command::new("sudo");
+        cmd.arg("pacman");
+        cmd.arg("-U");
+        cmd.args(files);
+        vec![cmd]
+    }
+
+    fn hold_commands(&self, held: &[&PackageOrGroup]) -> Vec<Command> {
+        let mut names: Vec<&str> = held.iter().map(|p_or_g| p_or_g.name.as_str()).collect();
+        names.sort();
+        vec![conf::ignore_command(&self.pacman_conf, &names)]
+    }
+
+    fn held_updates(&self, held: &[&PackageOrGroup]) -> Result<Vec<String>> {
+        let installed = database::read_local(&self.db_path)?;
+        let sync = self.sync_database()?;
+        Ok(held
+            .iter()
+            .filter_map(|p_or_g| {
+                let current = &installed.get(&p_or_g.name)?.version;
+                let available = &sync.find(&p_or_g.name)?.version;
+                (version::vercmp(available, current) == std::cmp::Ordering::Greater)
+                    .then(|| format!("{} is held at {}, {} is available", p_or_g.name, current, available))
+            })
+            .collect())
+    }
+
+    fn sweep_commands(&self) -> Result<Vec<Command>> {
+        if !self.sweep_orphans {
+            return Ok(Vec::new());
+        }
+        let orphans = database::orphans(&database::read_local(&self.db_path)?);
+        if orphans.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut cmd = Command::new("sudo");
+        cmd.arg("pacman");
+        cmd.arg(RemovalStrategy::RecursiveNosave.operation());
+        cmd.args(orphans);
+        Ok(vec![cmd])
+    }
+
+    fn removed_along(&self, to_delete: &[&PackageOrGroup]) -> Result<HashSet<PackageOrGroup>> {
+        if self.removal == RemovalStrategy::Remove && !self.sweep_orphans {
+            return Ok(HashSet::new());
+        }
+        let installed = database::read_local(&self.db_path)?;
+        let deleted: HashSet<String> = to_delete.iter().map(|p_or_g| p_or_g.name.clone()).collect();
+        let mut removed = if self.removal == RemovalStrategy::Remove {
+            HashSet::new()
+        } else {
+            database::removed_dependencies(&installed, &deleted)
+        };
+        if self.sweep_orphans {
+            let remaining = installed.into_iter().filter(|(name, _)| !deleted.contains(name) && !removed.contains(name)).collect();
+            removed.extend(database::orphans(&remaining));
+        }
+        // they are all dependencies, the ones no sync repository provides come from the AUR
+        let sync = self.sync_database()?;
+        Ok(removed
+            .into_iter()
+            .map(|name| {
+                let manager = if sync.find(&name).is_some() { PackageManager::PACMAN } else { PackageManager::AUR };
+                PackageOrGroup::new(name, manager).with_reason(InstallReason::Dependency)
+            })
+            .collect())
+    }
+
+    fn missing_archives(&self, to_add: &[&PackageOrGroup]) -> Vec<String> {
+        let archives: Vec<String> = self.archives.iter().map(|dir| dir.display().to_string()).collect();
+        to_add
+            .iter()
+            .filter_map(|&p_or_g| Some((p_or_g, Constraint::parse(p_or_g.version.as_ref()?).ok()?)))
+            .filter_map(|(p_or_g, constraint)| match p_or_g.manager {
+                PackageManager::PACMAN if find_archive(&self.archives, &p_or_g.name, &constraint).is_none() => {
+                    Some(format!("no package file for {}{} in {}", p_or_g.name, constraint, archives.join(", ")))
+                }
+                PackageManager::PACMAN => None,
+                // the AUR helper and pacman -U build or install the current version only
+                _ => Some(format!("cannot install {}{}: previous versions of {:?} packages are not kept", p_or_g.name, constraint, p_or_g.manager)),
+            })
+            .collect()
+    }
+
+    fn validate(&self, reference: &[&PackageOrGroup]) -> Result<Vec<String>> {
+        let mut problems = self.validate_archives(reference)?;
+        let sync = self.sync_database()?;
+        // without sync databases, nothing can be checked
+        if sync.is_empty() {
+            return Ok(problems);
+        }
+        for p_or_g in reference.iter().filter(|&p_or_g| p_or_g.manager == PackageManager::PACMAN) {
+            if let Some(repository) = &p_or_g.repository {
+                if !sync.packages.iter().any(|p| p.name == p_or_g.name && p.repository.as_ref() == Some(repository)) {
+                    problems.push(format!("package {} not found in repository {}", p_or_g.name, repository));
+                }
+                continue;
+            }
+            if let Resolution::Unknown(suggestions) = sync.resolve(&p_or_g.name) {
+                if suggestions.is_empty() {
+                    problems.push(format!("unknown package or group: {}", p_or_g.name));
+                } else {
+                    problems.push(format!("unknown package or group: {}, did you mean {}?", p_or_g.name, suggestions.join(", ")));
+                }
+            }
+        }
+        Ok(problems)
+    }
+
+    fn effects(&self, to_add: &[&PackageOrGroup], to_delete: &[&PackageOrGroup]) -> Result<Effects> {
+        let mut effects = Effects::default();
+
+        let (to_install, _) = split_installable(to_add);
+        let to_install: Vec<&String> = to_install.into_iter().map(|p_or_g| &p_or_g.name).collect();
+        if !to_install.is_empty() {
+            let mut cmd = Command::new("pacman");
+            cmd.args(["-S", "--print", "--noconfirm", "--print-format", "%n %s"]).args(&to_install);
+            if let Some(output) = run_simulation(cmd, &mut effects.warnings)? {
+                let installed = parse_pacman_print(output)?;
+                effects.download_size = installed.iter().map(|(_, size)| size).sum();
+                let names: Vec<&String> = installed.iter().map(|(name, _)| name).collect();
+                effects.installed_size = get_installed_size("-Si", &names)?;
+                // group members are also listed, only keep what is not requested
+                let requested = database::SyncDatabase::read(&self.db_path)?.groups();
+                effects.pulled_in = names
+                    .into_iter()
+                    .filter(|&name| !to_install.contains(&name) && !to_install.iter().any(|&g| requested.get(g).is_some_and(|m| m.contains(name))))
+                    .cloned()
+                    .collect();
+            }
+        }
+
+        if !to_delete.is_empty() {
+            let to_remove: Vec<&String> = to_delete.iter().map(|p_or_g| &p_or_g.name).collect();
+            let mut cmd = Command::new("pacman");
+            cmd.args(["-R", "--recursive", "--print", "--print-format", "%n"]).args(&to_remove);
+            if let Some(output) = run_simulation(cmd, &mut effects.warnings)? {
+                let dependencies = parse_pacman_packages(output)?
+                    .into_iter()
+                    .map(|p| p.name)
+                    .filter(|name| !to_remove.contains(&name))
+                    .collect();
+                // a recursive removal takes the dependencies with it
+                if self.removal == RemovalStrategy::Remove {
+                    effects.orphaned = dependencies;
+                } else {
+                    effects.removed_dependencies = dependencies;
+                }
+            }
+            let installed = database::read_local(&self.db_path)?;
+            effects.removed_size = to_remove
+                .into_iter()
+                .chain(effects.removed_dependencies.iter())
+                .filter_map(|name| installed.get(name))
+                .map(|p| p.size)
+                .sum();
+        }
+        Ok(effects)
+    }
+
+    fn simulate(&self, to_add: &[&PackageOrGroup], to_delete: &[&PackageOrGroup]) -> Result<Simulation> {
+        let mut simulation = Simulation::default();
+
+        let (to_install, to_build) = split_installable(to_add);
+        let to_install: Vec<String> = to_install.into_iter().map(target).collect();
+        if !to_install.is_empty() {
+            let mut cmd = Command::new("pacman");
+            cmd.args(["-S", "--print", "--noconfirm", "--print-format", "%n %v (%r)"]).args(&to_install);
+            if let Some(output) = run_simulation(cmd, &mut simulation.notes)? {
+                let (packages, notes) = parse_pacman_simulation(output)?;
+                simulation.installed.extend(packages);
+                simulation.notes.extend(notes);
+            }
+        }
+        simulation.installed.extend(to_build.into_iter().map(|p_or_g| format!("{} (aur, built with {})", p_or_g.name, self.aur_helper)));
+
+        if !to_delete.is_empty() {
+            let mut cmd = Command::new("pacman");
+            cmd.arg(self.removal.operation());
+            cmd.args(["--print", "--noconfirm", "--print-format", "%n %v"]);
+            cmd.args(to_delete.iter().map(|p_or_g| &p_or_g.name));
+            if let Some(output) = run_simulation(cmd, &mut simulation.notes)? {
+                let (packages, notes) = parse_pacman_simulation(output)?;
+                simulation.removed.extend(packages);
+                simulation.notes.extend(notes);
+            }
+        }
+        Ok(simulation)
+    }
+}
+
+impl Pacman {
+    /// Package file satisfying the version constraint of the package, when the archives are searched.
+    fn archive_for(&self, p_or_g: &PackageOrGroup) -> Option<PathBuf> {
+        if self.archives.is_empty() {
+            return None;
+        }
+        find_archive(&self.archives, &p_or_g.name, &Constraint::parse(p_or_g.version.as_ref()?).ok()?)
+    }
+
+    /// When the constraints are enforced, the installed versions not satisfying them must be
+    /// replaced by a package file from the archives.
+    fn validate_archives(&self, reference: &[&PackageOrGroup]) -> Result<Vec<String>> {
+        if self.archives.is_empty() || reference.iter().all(|p_or_g| p_or_g.version.is_none()) {
+            return Ok(Vec::new());
+        }
+        let installed = database::read_local(&self.db_path)?;
+        let unsatisfied: Vec<&PackageOrGroup> = reference
+            .iter()
+            .filter(|&p_or_g| {
+                let constraint = p_or_g.version.as_ref().and_then(|version| Constraint::parse(version).ok());
+                constraint.is_some_and(|constraint| installed.get(&p_or_g.name).is_some_and(|p| !constraint.is_satisfied_by(&p.version)))
+            })
+            .copied()
+            .collect();
+        Ok(self.missing_archives(&unsatisfied))
+    }
+}
+
+/// Newest package file of the archives satisfying the constraint, signatures are skipped.
+fn find_archive(archives: &[PathBuf], name: &str, constraint: &Constraint) -> Option<PathBuf> {
+    archives
+        .iter()
+        .filter_map(|dir| std::fs::read_dir(dir).ok())
+        .flatten()
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter_map(|path| {
+            let version = package_file_version(&path.file_name()?.to_string_lossy(), name)?;
+            constraint.is_satisfied_by(&version).then_some((version, path))
+        })
+        .max_by(|(a, _), (b, _)| version::vercmp(a, b))
+        .map(|(_, path)| path)
+}
+
+/// Version of a package file named `<name>-<version>-<release>-<arch>.pkg.tar.<ext>`,
+/// none for another package or a signature.
+fn package_file_version(file_name: &str, name: &str) -> Option<String> {
+    let (package, extension) = file_name.strip_prefix(name)?.strip_prefix('-')?.split_once(".pkg.tar")?;
+    if extension.ends_with(".sig") {
+        return None;
+    }
+    let (version, _arch) = package.rsplit_once('-')?;
+    // neither the version nor the release contain a dash, unlike the name of another package
+    if version.matches('-').count() != 1 {
+        return None;
+    }
+    Some(version.to_string())
+}
+
+/// Name given to pacman, prefixed by the declared repository: `extra/firefox`.
+fn target(p_or_g: &PackageOrGroup) -> String {
+    match (&p_or_g.manager, &p_or_g.repository) {
+        (PackageManager::PACMAN, Some(repository)) => format!("{}/{}", repository, p_or_g.name),
+        _ => p_or_g.name.clone(),
+    }
+}
+
+impl RemovalStrategy {
+    fn operation(&self) -> &'static str {
+        match self {
+            RemovalStrategy::Remove => "-R",
+            RemovalStrategy::Recursive => "-Rs",
+            RemovalStrategy::RecursiveNosave => "-Rns",
+        }
+    }
+}
+
+/// Split the packages to install between the ones pacman knows and the ones built by the AUR helper:
+/// AUR packages are unknown to pacman until they are built, `local/` packages are never installed.
+fn split_installable<'a>(to_add: &[&'a PackageOrGroup]) -> (Vec<&'a PackageOrGroup>, Vec<&'a PackageOrGroup>) {
+    let packages = |manager: PackageManager| to_add.iter().copied().filter(|p_or_g| p_or_g.manager == manager).collect();
+    (packages(PackageManager::PACMAN), packages(PackageManager::AUR))
+}
+
+/// Run a pacman simulation, the messages of pacman are kept as warnings
+/// and a failure is not an error since it is what would happen.
+fn run_simulation(mut cmd: Command, warnings: &mut Vec<String>) -> Result<Option<Vec<u8>>> {
+    let output = cmd.env("LC_ALL", "C").output()?;
+    let stderr = String::from(str::from_utf8(&output.stderr)?);
+    warnings.extend(stderr.lines().filter(|line| !line.is_empty()).map(|line| line.to_string()));
+    if output.status.success() {
+        return Ok(Some(output.stdout));
+    }
+    Ok(None)
+}
+
+/// Sum of the installed size of the packages, from `pacman -Si`.
+fn get_installed_size(operation: &str, names: &[&String]) -> Result<u64> {
+    if names.is_empty() {
+        return Ok(0);
+    }
+    let output = Command::new("pacman").env("LC_ALL", "C").arg(operation).args(names).output()?;
+    parse_pacman_installed_size(output.stdout)
+}
+
+/// Installed packages, with their install reason and the repositories providing them.
+pub fn get_installed_packages(db_path: &Path) -> Result<HashSet<Package>> {
+    Ok(installed_packages(database::read_local(db_path)?, &database::SyncDatabase::read(db_path)?))
+}
+
+pub fn get_explicit_installed_packages(db_path: &Path) -> Result<HashSet<Package>> {
+    Ok(get_installed_packages(db_path)?.into_iter().filter(|p| p.reason == InstallReason::Explicit).collect())
+}
+
+/// The local database does not record the repository of a package: it is found by looking for
+/// the installed version in the sync databases. Like `pacman -Qm`, packages that no sync repository
+/// provides are foreign, they come from the AUR.
+fn installed_packages(installed: HashMap<String, database::DatabasePackage>, sync: &database::SyncDatabase) -> HashSet<Package> {
+    let required = database::required(&installed);
+    installed
+        .into_values()
+        .map(|p| {
+            let reason = if p.explicit { InstallReason::Explicit } else { InstallReason::Dependency };
+            let is_required = required.contains(&p.name);
+            let repositories = sync
+                .packages
+                .iter()
+                .filter(|s| s.name == p.name && s.version == p.version)
+                .filter_map(|s| s.repository.clone())
+                .collect();
+            let manager = if sync.find(&p.name).is_some() { PackageManager::PACMAN } else { PackageManager::AUR };
+            Package::new(p.name, p.groups, manager)
+                .with_reason(reason)
+                .with_repositories(repositories)
+                .with_version(p.version)
+                .with_required(is_required)
+        })
+        .collect()
+}
+
+fn parse_pacman_packages(output: Vec<u8>) -> Result<HashSet<Package>> {
+   let utf8_output = String::from(str::from_utf8(&output)?);
+   Ok(HashSet::from_iter(utf8_output.split("\n").filter(|row| !row.is_empty()).map(|row| Package::new(row.to_string(), Vec::new(), PackageManager::PACMAN))))
+}
+
+fn parse_pacman_print(output: Vec<u8>) -> Result<Vec<(String, u64)>> {
+    let utf8_output = String::from(str::from_utf8(&output)?);
+    let mut packages = Vec::new();
+    for row in utf8_output.split('\n').filter(|row| !row.is_empty()) {
+        let values: Vec<&str> = row.split(' ').collect();
+        let size = values.get(1).and_then(|size| size.parse::<u64>().ok());
+        match (values.len(), size) {
+            (2, Some(size)) => packages.push((values[0].to_string(), size)),
+            _ => return Err(BackendError::ParseError(format!("cannot read pacman print: {}", row))),
+        }
+    }
+    Ok(packages)
+}
+
+/// Split the output of `pacman --print --noconfirm` between the printed packages and the
+/// questions pacman answered by itself (`:: ... are in conflict`, `:: Replace ...`).
+fn parse_pacman_simulation(output: Vec<u8>) -> Result<(Vec<String>, Vec<String>)> {
+    let utf8_output = String::from(str::from_utf8(&output)?);
+    let mut packages = Vec::new();
+    let mut notes = Vec::new();
+    for row in utf8_output.lines().filter(|row| !row.is_empty()) {
+        if let Some(note) = row.strip_prefix("::") {
+            notes.push(note.trim().to_string());
+        } else if row.starts_with("warning:") || row.starts_with("error:") {
+            notes.push(row.to_string());
+        } else {
+            packages.push(row.to_string());
+        }
+    }
+    Ok((packages, notes))
+}
+
+fn parse_pacman_installed_size(output: Vec<u8>) -> Result<u64> {
+    let utf8_output = String::from(str::from_utf8(&output)?);
+    let mut total = 0;
+    for row in utf8_output.lines().filter(|row| row.starts_with("Installed Size")) {
+        let size = row.split_once(':').map(|(_, size)| size.trim()).unwrap_or_default();
+        total += parse_size(size).ok_or(BackendError::ParseError(format!("cannot read pacman size: {}", row)))?;
+    }
+    Ok(total)
+}
+
+/// Parse a size as displayed by pacman: `12.34 MiB`.
+fn parse_size(raw: &str) -> Option<u64> {
+    let (value, unit) = raw.split_once(' ')?;
+    let value: f64 = value.parse().ok()?;
+    let factor = match unit {
+        "B" => 1.0,
+        "KiB" => 1024.0,
+        "MiB" => 1024.0 * 1024.0,
+        "GiB" => 1024.0 * 1024.0 * 1024.0,
+        "TiB" => 1024.0 * 1024.0 * 1024.0 * 1024.0,
+        _ => return None,
+    };
+    Some((value * factor) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+    use super::*;
+
+    #[test]
+    fn parse_packages_nominal() {
+        // Given
+        let output = fs::read(Path::new("tests/data/pacman_packages_output"));
+        let mut expected = HashSet::<Package>::new();
+        expected.insert(Package::new("amd-ucode".to_string(), Vec::new(), PackageManager::PACMAN));
+        expected.insert(Package::new("baobab".to_string(), Vec::new(), PackageManager::PACMAN));
+        expected.insert(Package::new("base".to_string(), Vec::new(), PackageManager::PACMAN));
+        expected.insert(Package::new("blender".to_string(), Vec::new(), PackageManager::PACMAN));
+
+        // When
+        let packages = parse_pacman_packages(output.unwrap()).unwrap();
+
+        // Then
+        assert_eq!(packages, expected)
+    }
+
+    #[test]
+    fn install_commands_skip_local_packages() {
+        // Given
+        let pacman = PackageOrGroup::new("vim".to_string(), PackageManager::PACMAN);
+        let local = PackageOrGroup::new("pacsync".to_string(), PackageManager::LOCAL);
+
+        // When
+        let commands = Pacman::new("paru".to_string()).install_commands(&[&pacman, &local], &[]);
+        let only_local = Pacman::new("paru".to_string()).install_commands(&[&local], &[]);
+
+        // Then
+        assert_eq!(commands.len(), 1);
+        assert_eq!(format!("{:?}", commands[0]), "\"sudo\" \"pacman\" \"-S\" \"vim\"");
+        assert!(only_local.is_empty());
+    }
+
+    #[test]
+    fn install_commands_as_dependencies() {
+        // Given
+        let vim = PackageOrGroup::new("vim".to_string(), PackageManager::PACMAN);
+        let python = PackageOrGroup::new("python".to_string(), PackageManager::PACMAN).with_reason(InstallReason::Dependency);
+
+        // When
+        let commands = Pacman::new("paru".to_string()).install_commands(&[&vim, &python], &[]);
+
+        // Then
+        let commands: Vec<String> = commands.iter().map(|cmd| format!("{:?}", cmd)).collect();
+        assert_eq!(commands, vec![
+            "\"sudo\" \"pacman\" \"-S\" \"vim\"",
+            "\"sudo\" \"pacman\" \"-S\" \"--asdeps\" \"python\"",
+        ]);
+    }
+
+    #[test]
+    fn install_commands_from_repository() {
+        // Given
+        let firefox = PackageOrGroup::new("firefox".to_string(), PackageManager::PACMAN).with_repository("private".to_string());
+
+        // When
+        let commands = Pacman::new("paru".to_string()).install_commands(&[&firefox], &[]);
+
+        // Then
+        assert_eq!(format!("{:?}", commands[0]), "\"sudo\" \"pacman\" \"-S\" \"private/firefox\"");
+    }
+
+    #[test]
+    fn install_commands_ignore_excluded() {
+        // Given
+        let gnome = PackageOrGroup::new("gnome".to_string(), PackageManager::PACMAN);
+        let epiphany = PackageOrGroup::new("epiphany".to_string(), PackageManager::PACMAN);
+
+        // When
+        let commands = Pacman::new("paru".to_string()).install_commands(&[&gnome], &[&epiphany]);
+
+        // Then
+        assert_eq!(format!("{:?}", commands[0]), "\"sudo\" \"pacman\" \"-S\" \"gnome\" \"--ignore\" \"epiphany\"");
+    }
+
+    #[test]
+    fn validate_unknown_packages() {
+        // Given
+        let pacman = Pacman::new("paru".to_string()).with_db_path(PathBuf::from("tests/data"));
+        let known = PackageOrGroup::new("vim".to_string(), PackageManager::PACMAN);
+        let group = PackageOrGroup::new("gnome".to_string(), PackageManager::PACMAN);
+        let typo = PackageOrGroup::new("firefx".to_string(), PackageManager::PACMAN);
+        let aur = PackageOrGroup::new("paru-bin".to_string(), PackageManager::AUR);
+        let pinned = PackageOrGroup::new("vim".to_string(), PackageManager::PACMAN).with_repository("extra".to_string());
+        let wrong_repository = PackageOrGroup::new("firefox".to_string(), PackageManager::PACMAN).with_repository("core".to_string());
+
+        // When
+        let problems = pacman.validate(&[&known, &group, &typo, &aur, &pinned, &wrong_repository]).unwrap();
+
+        // Then
+        assert_eq!(problems, vec![
+            "unknown package or group: firefx, did you mean firefox?".to_string(),
+            "package firefox not found in repository core".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn held_packages() {
+        // Given
+        let pacman = Pacman::new("paru".to_string())
+            .with_db_path(PathBuf::from("tests/data"))
+            .with_pacman_conf(PathBuf::from("tests/data/pacman.conf"));
+        let linux = PackageOrGroup::new("linux".to_string(), PackageManager::PACMAN);
+        let vim = PackageOrGroup::new("vim".to_string(), PackageManager::PACMAN);
+
+        // When
+        let held = pacman.get_held_packages().unwrap();
+        let updates = pacman.held_updates(&[&linux, &vim]).unwrap();
+
+        // Then
+        let names: HashSet<String> = held.into_iter().map(|p| p.name).collect();
+        assert_eq!(names, HashSet::from(["linux-lts".to_string(), "nvidia-dkms".to_string()]));
+        assert_eq!(updates, vec!["linux is held at 6.9.6.arch1-1, 6.9.7.arch1-1 is available".to_string()]);
+    }
+
+    #[test]
+    fn mark_commands_by_reason() {
+        // Given
+        let glibc = PackageOrGroup::new("glibc".to_string(), PackageManager::PACMAN).with_reason(InstallReason::Dependency);
+        let python = PackageOrGroup::new("python".to_string(), PackageManager::PACMAN);
+
+        // When
+        let commands = Pacman::new("paru".to_string()).mark_commands(&[&python, &glibc]);
+
+        // Then
+        let commands: Vec<String> = commands.iter().map(|cmd| format!("{:?}", cmd)).collect();
+        assert_eq!(commands, vec![
+            "\"sudo\" \"pacman\" \"-D\" \"--asdeps\" \"glibc\"",
+            "\"sudo\" \"pacman\" \"-D\" \"--asexplicit\" \"python\"",
+        ]);
+    }
+
+    #[test]
+    fn remove_commands_with_strategy() {
+        // Given
+        let vim = PackageOrGroup::new("vim".to_string(), PackageManager::PACMAN);
+
+        // When
+        let simple = Pacman::new("paru".to_string()).remove_commands(&[&vim]);
+        let recursive = Pacman::new("paru".to_string()).with_removal(RemovalStrategy::RecursiveNosave).remove_commands(&[&vim]);
+
+        // Then
+        assert_eq!(format!("{:?}", simple[0]), "\"sudo\" \"pacman\" \"-R\" \"vim\"");
+        assert_eq!(format!("{:?}", recursive[0]), "\"sudo\" \"pacman\" \"-Rns\" \"vim\"");
+    }
+
+    #[test]
+    fn install_commands_aur_with_helper() {
+        // Given
+        let aur = PackageOrGroup::new("paru-bin".to_string(), PackageManager::AUR);
+
+        // When
+        let commands = Pacman::new("yay".to_string()).install_commands(&[&aur], &[]);
+
+        // Then
+        assert_eq!(commands.len(), 1);
+        assert!(format!("{:?}", commands[0]).ends_with("\"yay\" \"-S\" \"--aur\" \"paru-bin\""));
+    }
+
+    #[test]
+    fn parse_print_nominal() {
+        // Given
+        let output = fs::read(Path::new("tests/data/pacman_print_output"));
+        let expected = vec![
+            ("libfoo".to_string(), 1024),
+            ("vim".to_string(), 2048000),
+        ];
+
+        // When
+        let packages = parse_pacman_print(output.unwrap()).unwrap();
+
+        // Then
+        assert_eq!(packages, expected)
+    }
+
+    #[test]
+    fn parse_simulation_nominal() {
+        // Given
+        let output = fs::read(Path::new("tests/data/pacman_simulation_output"));
+
+        // When
+        let (packages, notes) = parse_pacman_simulation(output.unwrap()).unwrap();
+
+        // Then
+        assert_eq!(packages, vec![
+            "libfoo 1.2-1 (extra)".to_string(),
+            "vim 9.1.0-1 (extra)".to_string(),
+        ]);
+        assert_eq!(notes, vec![
+            "Replace vi with extra/vim? [Y/n]".to_string(),
+            "gvim and vim are in conflict. Remove gvim? [y/N]".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn parse_installed_size_nominal() {
+        // Given
+        let output = fs::read(Path::new("tests/data/pacman_info_output"));
+
+        // When
+        let size = parse_pacman_installed_size(output.unwrap()).unwrap();
+
+        // Then
+        assert_eq!(size, 512 + 1536 + 2 * 1024 * 1024)
+    }
+
+    #[test]
+    fn installed_packages_nominal() {
+        // Given
+        let installed = database::read_local(Path::new("tests/data")).unwrap();
+        let mut expected = HashSet::<String>::new();
+        expected.insert("baobab".to_string());
+        expected.insert("base".to_string());
+        expected.insert("linux".to_string());
+
+        // When
+        let sync = database::SyncDatabase::read(Path::new("tests/data")).unwrap();
+        let packages = installed_packages(installed, &sync);
+
+        // Then
+        assert_eq!(packages.len(), 7);
+        let explicit: HashSet<String> = packages.iter().filter(|p| p.reason == InstallReason::Explicit).map(|p| p.name.clone()).collect();
+        assert_eq!(explicit, expected);
+        let baobab = packages.iter().find(|p| p.name == "baobab").unwrap();
+        assert_eq!(baobab.groups, vec!["gnome".to_string()]);
+        assert_eq!(baobab.repositories, vec!["extra".to_string()]);
+        assert_eq!(baobab.version, Some("46.0-1".to_string()));
+        assert_eq!(baobab.manager, PackageManager::PACMAN);
+        let libopt = packages.iter().find(|p| p.name == "libopt").unwrap();
+        assert!(libopt.repositories.is_empty());
+        assert_eq!(libopt.manager, PackageManager::AUR);
+        assert!(packages.iter().find(|p| p.name == "glibc").unwrap().required);
+        assert!(!baobab.required);
+    }
+
+    #[test]
+    fn foreign_packages_from_aur() {
+        // Given
+        let mut installed = database::read_local(Path::new("tests/data")).unwrap();
+        let paru = database::DatabasePackage{name: "paru-bin".to_string(), explicit: true, ..Default::default()};
+        installed.insert(paru.name.clone(), paru);
+
+        // When
+        let sync = database::SyncDatabase::read(Path::new("tests/data")).unwrap();
+        let packages = installed_packages(installed, &sync);
+
+        // Then
+        let paru = packages.iter().find(|p| p.name == "paru-bin").unwrap();
+        let base = packages.iter().find(|p| p.name == "base").unwrap();
+        assert_eq!(paru.manager, PackageManager::AUR);
+        assert_eq!(base.manager, PackageManager::PACMAN);
+    }
+
+    #[test]
+    fn version_commands_from_archives() {
+        // Given
+        let pacman = Pacman::new("paru".to_string()).with_archives(vec![PathBuf::from("tests/data/archive")]);
+        let linux = PackageOrGroup::new("linux-lts".to_string(), PackageManager::PACMAN).with_version("=6.6.*".to_string());
+        let vim = PackageOrGroup::new("vim".to_string(), PackageManager::PACMAN).with_version("<9".to_string());
+
+        // When
+        let commands = pacman.version_commands(&[&linux, &vim]);
+
+        // Then
+        let commands: Vec<String> = commands.iter().map(|cmd| format!("{:?}", cmd)).collect();
+        assert_eq!(commands, vec!["\"sudo\" \"pacman\" \"-U\" \"tests/data/archive/linux-lts-6.6.35-1-x86_64.pkg.tar.zst\""]);
+    }
+
+    #[test]
+    fn removed_along_with_dependencies_and_orphans() {
+        // Given
+        let base = PackageOrGroup::new("base".to_string(), PackageManager::PACMAN);
+        let pacman = Pacman::new("paru".to_string()).with_db_path(PathBuf::from("tests/data"));
+
+        // When
+        let removed = pacman.removed_along(&[&base]).unwrap();
+        let recursive = Pacman { removal: RemovalStrategy::Recursive, ..pacman }.removed_along(&[&base]).unwrap();
+        let swept = Pacman::new("paru".to_string()).with_db_path(PathBuf::from("tests/data")).with_removal(RemovalStrategy::Recursive)
+            .with_orphan_sweep(true).removed_along(&[&base]).unwrap();
+
+        // Then
+        let names = |removed: HashSet<PackageOrGroup>| -> HashSet<String> { removed.into_iter().map(|p| p.name).collect() };
+        assert!(removed.is_empty());
+        assert_eq!(recursive.iter().next().map(|p| p.reason), Some(InstallReason::Dependency));
+        assert_eq!(names(recursive), HashSet::from(["bash".to_string()]));
+        assert_eq!(names(swept), HashSet::from(["bash".to_string(), "libunused".to_string()]));
+    }
+
+    #[test]
+    fn missing_archives_for_previous_versions() {
+        // Given
+        let pacman = Pacman::new("paru".to_string()).with_archives(vec![PathBuf::from("tests/data/archive")]);
+        let linux = PackageOrGroup::new("linux-lts".to_string(), PackageManager::PACMAN).with_version("=6.6.30-1".to_string());
+        let vim = PackageOrGroup::new("vim".to_string(), PackageManager::PACMAN).with_version("=9.1.0-1".to_string());
+        let paru = PackageOrGroup::new("paru-bin".to_string(), PackageManager::AUR).with_version("=2.0.3-1".to_string());
+
+        // When
+        let missing = pacman.missing_archives(&[&linux, &vim, &paru]);
+
+        // Then
+        assert_eq!(missing, vec![
+            "no package file for vim=9.1.0-1 in tests/data/archive",
+            "cannot install paru-bin=2.0.3-1: previous versions of AUR packages are not kept",
+        ]);
+    }
+
+    #[test]
+    fn install_commands_from_archives() {
+        // Given
+        let pacman = Pacman::new("paru".to_string()).with_archives(vec![PathBuf::from("tests/data/archive")]);
+        let linux = PackageOrGroup::new("linux-lts".to_string(), PackageManager::PACMAN).with_version("=6.6.30-1".to_string());
+        let vim = PackageOrGroup::new("vim".to_string(), PackageManager::PACMAN).with_version(">=9".to_string());
+
+        // When
+        let commands = pacman.install_commands(&[&linux, &vim], &[]);
+
+        // Then
+        let commands: Vec<String> = commands.iter().map(|cmd| format!("{:?}", cmd)).collect();
+        assert_eq!(commands, vec![
+            "\"sudo\" \"pacman\" \"-U\" \"tests/data/archive/linux-lts-6.6.30-1-x86_64.pkg.tar.zst\"",
+            "\"sudo\" \"pacman\" \"-S\" \"vim\"",
+        ]);
+    }
+
+    #[test]
+    fn package_file_versions() {
+        assert_eq!(package_file_version("linux-lts-6.6.35-1-x86_64.pkg.tar.zst", "linux-lts"), Some("6.6.35-1".to_string()));
+        assert_eq!(package_file_version("vim-1:9.1.0-1-x86_64.pkg.tar.xz", "vim"), Some("1:9.1.0-1".to_string()));
+        assert_eq!(package_file_version("linux-lts-6.6.35-1-x86_64.pkg.tar.zst.sig", "linux-lts"), None);
+        assert_eq!(package_file_version("linux-lts-headers-6.6.35-1-x86_64.pkg.tar.zst", "linux-lts"), None);
+        assert_eq!(package_file_version("linux-6.9.7.arch1-1-x86_64.pkg.tar.zst", "linux-lts"), None);
+    }
+}
@@ -0,0 +1,95 @@
+/*
+ *  backend/pacman/conf.rs
+ *
+ *  Copyright (c) 2024-2024 Cédric ROYER <cedric dot royer at zaclys dot net>
+ *
+ *  This program is free software; you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation; either version 2 of the License, or
+ *  (at your option) any later version.
+ *
+ *  This program is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::process::Command;
+use crate::backend::Result;
+
+pub const DEFAULT_PACMAN_CONF: &str = "/etc/pacman.conf";
+
+/// End of the `IgnorePkg` line written by pacsync, pacman ignores everything after a `#`.
+const MANAGED_MARKER: &str = "# managed by pacsync";
+
+/// Packages of the `IgnorePkg` line managed by pacsync, the other `IgnorePkg` lines are left alone.
+pub fn managed_ignored(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .filter_map(|line| line.trim_end().strip_suffix(MANAGED_MARKER))
+        .filter_map(|line| line.trim().strip_prefix("IgnorePkg"))
+        .filter_map(|line| line.trim_start().strip_prefix('='))
+        .flat_map(|names| names.split_whitespace())
+        .map(|name| name.to_string())
+        .collect()
+}
+
+/// Read the managed `IgnorePkg` line, nothing is held without configuration file.
+pub fn read_managed_ignored(path: &Path) -> Result<Vec<String>> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(managed_ignored(&content)),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Command replacing the managed `IgnorePkg` line, written at the beginning of the `[options]` section.
+/// Without packages, the line is only removed.
+pub fn ignore_command(path: &Path, names: &[&str]) -> Command {
+    let mut cmd = Command::new("sudo");
+    cmd.arg("sed");
+    cmd.arg("-i");
+    cmd.arg("-e");
+    cmd.arg(format!("/{}$/d", MANAGED_MARKER));
+    if !names.is_empty() {
+        cmd.arg("-e");
+        cmd.arg(format!("/^\\[options\\]/a IgnorePkg = {} {}", names.join(" "), MANAGED_MARKER));
+    }
+    cmd.arg(path);
+    cmd
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn managed_line_only() {
+        // Given
+        let content = fs::read_to_string("tests/data/pacman.conf").unwrap();
+
+        // When
+        let ignored = managed_ignored(&content);
+
+        // Then
+        assert_eq!(ignored, vec!["linux-lts".to_string(), "nvidia-dkms".to_string()]);
+    }
+
+    #[test]
+    fn ignore_command_replaces_managed_line() {
+        // When
+        let hold = ignore_command(Path::new("/etc/pacman.conf"), &["linux-lts", "mesa"]);
+        let release = ignore_command(Path::new("/etc/pacman.conf"), &[]);
+
+        // Then
+        assert_eq!(format!("{:?}", hold),
+            r#""sudo" "sed" "-i" "-e" "/# managed by pacsync$/d" "-e" "/^\\[options\\]/a IgnorePkg = linux-lts mesa # managed by pacsync" "/etc/pacman.conf""#);
+        assert_eq!(format!("{:?}", release), r#""sudo" "sed" "-i" "-e" "/# managed by pacsync$/d" "/etc/pacman.conf""#);
+    }
+}
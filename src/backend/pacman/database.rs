@@ -0,0 +1,414 @@
+/*
+ *  backend/pacman/database.rs
+ *
+ *  Copyright (c) 2024-2024 Cédric ROYER <cedric dot royer at zaclys dot net>
+ *
+ *  This program is free software; you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation; either version 2 of the License, or
+ *  (at your option) any later version.
+ *
+ *  This program is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufReader, Read};
+use std::path::Path;
+use flate2::read::GzDecoder;
+use crate::backend::{BackendError, Result};
+
+/// How a declared name is found in the sync databases.
+#[derive(PartialEq, Eq, Debug)]
+pub enum Resolution {
+    Package(String),
+    Group,
+    /// the name is only provided by other packages
+    Provider(Vec<String>),
+    /// the name is unknown, with close names as suggestions
+    Unknown(Vec<String>),
+}
+
+/// Packages of the sync databases (`<dbpath>/sync/*.db`).
+#[derive(Debug, Default)]
+pub struct SyncDatabase {
+    pub packages: Vec<DatabasePackage>,
+    /// positions of the packages of each name, in repository order
+    index: HashMap<String, Vec<usize>>,
+}
+
+/// A package of a pacman database, as described by its `desc` file.
+#[derive(PartialEq, Eq, Debug, Default, Clone)]
+pub struct DatabasePackage {
+    pub name: String,
+    pub version: String,
+    /// installed explicitly, always true for the packages of a sync database
+    pub explicit: bool,
+    pub groups: Vec<String>,
+    /// repository of the package, the local database does not record it
+    pub repository: Option<String>,
+    /// installed size, in bytes
+    pub size: u64,
+    /// dependencies, without their version constraint
+    pub depends: Vec<String>,
+    /// optional dependencies, without their description
+    pub optdepends: Vec<String>,
+    /// virtual packages provided, without their version
+    pub provides: Vec<String>,
+}
+
+/// Read the local database (`<dbpath>/local/*/desc`), the installed packages indexed by name.
+pub fn read_local(db_path: &Path) -> Result<HashMap<String, DatabasePackage>> {
+    let mut packages = HashMap::new();
+    for entry in fs::read_dir(db_path.join("local"))? {
+        let path = entry?.path();
+        // the database also contains an ALPM_DB_VERSION file
+        if !path.is_dir() {
+            continue;
+        }
+        let package = parse_desc(&fs::read_to_string(path.join("desc"))?, None)?;
+        packages.insert(package.name.clone(), package);
+    }
+    Ok(packages)
+}
+
+impl SyncDatabase {
+    pub fn new(packages: Vec<DatabasePackage>) -> SyncDatabase {
+        let mut index: HashMap<String, Vec<usize>> = HashMap::new();
+        for (position, package) in packages.iter().enumerate() {
+            index.entry(package.name.clone()).or_default().push(position);
+        }
+        SyncDatabase{packages, index}
+    }
+
+    /// Read every sync database, ordered by repository name.
+    pub fn read(db_path: &Path) -> Result<SyncDatabase> {
+        let sync = db_path.join("sync");
+        if !sync.is_dir() {
+            return Ok(SyncDatabase::default());
+        }
+        let mut files: Vec<_> = fs::read_dir(sync)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<std::io::Result<_>>()?;
+        files.retain(|path| path.extension().is_some_and(|e| e == "db"));
+        files.sort();
+        let mut packages = Vec::new();
+        for file in files {
+            let repository = file.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+            packages.extend(read_sync_file(&file, &repository)?);
+        }
+        Ok(SyncDatabase::new(packages))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.packages.is_empty()
+    }
+
+    /// First package with this name, in repository order.
+    pub fn find(&self, name: &str) -> Option<&DatabasePackage> {
+        self.find_all(name).next()
+    }
+
+    /// Packages with this name, in repository order.
+    pub fn find_all<'a>(&'a self, name: &str) -> impl Iterator<Item = &'a DatabasePackage> {
+        self.index.get(name).into_iter().flatten().map(|&position| &self.packages[position])
+    }
+
+    /// Members of every group.
+    pub fn groups(&self) -> HashMap<String, HashSet<String>> {
+        let mut groups: HashMap<String, HashSet<String>> = HashMap::new();
+        for package in self.packages.iter() {
+            for group in package.groups.iter() {
+                groups.entry(group.clone()).or_default().insert(package.name.clone());
+            }
+        }
+        groups
+    }
+
+    /// Repository of every package, the first one when several provide it.
+    pub fn repositories(&self) -> HashMap<String, String> {
+        let mut repositories = HashMap::new();
+        for package in self.packages.iter() {
+            if let Some(repository) = &package.repository {
+                repositories.entry(package.name.clone()).or_insert_with(|| repository.clone());
+            }
+        }
+        repositories
+    }
+
+    /// Find a declared name as a package, a group or a virtual package.
+    pub fn resolve(&self, name: &str) -> Resolution {
+        if let Some(package) = self.find(name) {
+            return Resolution::Package(package.repository.clone().unwrap_or_default());
+        }
+        if self.packages.iter().any(|p| p.groups.iter().any(|g| g == name)) {
+            return Resolution::Group;
+        }
+        let providers: Vec<String> = self.packages.iter().filter(|p| p.provides.iter().any(|v| v == name)).map(|p| p.name.clone()).collect();
+        if !providers.is_empty() {
+            return Resolution::Provider(providers);
+        }
+        Resolution::Unknown(self.suggestions(name))
+    }
+
+    /// At most three known names close to the given one.
+    fn suggestions(&self, name: &str) -> Vec<String> {
+        let mut candidates: BTreeSet<(usize, &String)> = BTreeSet::new();
+        let names = self.packages.iter().flat_map(|p| std::iter::once(&p.name).chain(p.groups.iter()));
+        for candidate in names {
+            let distance = strsim::levenshtein(name, candidate);
+            if distance <= MAX_SUGGESTION_DISTANCE {
+                candidates.insert((distance, candidate));
+            }
+        }
+        let mut suggestions: Vec<String> = Vec::new();
+        for (_, candidate) in candidates {
+            if !suggestions.contains(candidate) {
+                suggestions.push(candidate.clone());
+            }
+        }
+        suggestions.truncate(3);
+        suggestions
+    }
+}
+
+const MAX_SUGGESTION_DISTANCE: usize = 2;
+
+/// Read a sync database: a tar archive, possibly gzipped, with a `desc` file per package.
+fn read_sync_file(file: &Path, repository: &str) -> Result<Vec<DatabasePackage>> {
+    let mut reader = BufReader::new(File::open(file)?);
+    let mut magic = [0u8; 2];
+    reader.read_exact(&mut magic)?;
+    let reader = BufReader::new(File::open(file)?);
+    let archive: Box<dyn Read> = if magic == [0x1f, 0x8b] {
+        Box::new(GzDecoder::new(reader))
+    } else {
+        Box::new(reader)
+    };
+
+    // older databases split the description in desc and depends files
+    let mut entries: BTreeMap<String, String> = BTreeMap::new();
+    let mut archive = tar::Archive::new(archive);
+    for entry in archive.entries().map_err(|err| invalid_database(file, err))? {
+        let mut entry = entry.map_err(|err| invalid_database(file, err))?;
+        let path = entry.path().map_err(|err| invalid_database(file, err))?.to_path_buf();
+        if !path.ends_with("desc") && !path.ends_with("depends") {
+            continue;
+        }
+        let directory = path.parent().map(|p| p.to_string_lossy().to_string()).unwrap_or_default();
+        let mut content = String::new();
+        entry.read_to_string(&mut content).map_err(|err| invalid_database(file, err))?;
+        let description = entries.entry(directory).or_default();
+        description.push_str(&content);
+        description.push('\n');
+    }
+    entries.values().map(|content| parse_desc(content, Some(repository))).collect()
+}
+
+fn invalid_database(file: &Path, err: std::io::Error) -> BackendError {
+    BackendError::ParseError(format!("cannot read sync database {}: {}", file.display(), err))
+}
+
+/// Parse a `desc` file: `%FIELD%` headers followed by one value per line, ended by an empty line.
+pub fn parse_desc(content: &str, repository: Option<&str>) -> Result<DatabasePackage> {
+    let mut package = DatabasePackage {
+        explicit: true,
+        repository: repository.map(|r| r.to_string()),
+        ..Default::default()
+    };
+    let mut field = "";
+    for line in content.lines() {
+        if line.is_empty() {
+            field = "";
+        } else if line.starts_with('%') && line.ends_with('%') && field.is_empty() {
+            field = line;
+        } else {
+            match field {
+                "%NAME%" => package.name = line.to_string(),
+                "%VERSION%" => package.version = line.to_string(),
+                "%REASON%" => package.explicit = line != "1",
+                "%GROUPS%" => package.groups.push(line.to_string()),
+                "%SIZE%" | "%ISIZE%" => {
+                    package.size = line.parse().map_err(|_| BackendError::ParseError(format!("cannot read package size: {}", line)))?
+                }
+                "%DEPENDS%" => package.depends.push(strip_version(line).to_string()),
+                "%OPTDEPENDS%" => package.optdepends.push(line.split(':').next().unwrap_or(line).to_string()),
+                "%PROVIDES%" => package.provides.push(strip_version(line).to_string()),
+                _ => (),
+            }
+        }
+    }
+    if package.name.is_empty() {
+        return Err(BackendError::ParseError(format!("package without name in desc:\n{}", content)));
+    }
+    Ok(package)
+}
+
+/// Remove the version constraint of a dependency: `glibc>=2.39` is `glibc`.
+fn strip_version(dependency: &str) -> &str {
+    dependency.split(['<', '>', '=']).next().unwrap_or(dependency)
+}
+
+/// Installed packages required by another installed package, by name or through a provided name.
+/// Optional dependencies are not taken into account, pacman removes them.
+pub fn required(installed: &HashMap<String, DatabasePackage>) -> HashSet<String> {
+    let depends: HashSet<&String> = installed.values().flat_map(|p| p.depends.iter()).collect();
+    installed
+        .values()
+        .filter(|p| depends.contains(&p.name) || p.provides.iter().any(|provided| depends.contains(provided)))
+        .map(|p| p.name.clone())
+        .collect()
+}
+
+/// Packages installed as dependencies that are neither required nor optionally required
+/// by any installed package, like `pacman -Qdt`.
+pub fn orphans(installed: &HashMap<String, DatabasePackage>) -> HashSet<String> {
+    let required: HashSet<&String> = installed
+        .values()
+        .flat_map(|p| p.depends.iter().chain(p.optdepends.iter()))
+        .collect();
+    installed
+        .values()
+        .filter(|p| !p.explicit)
+        .filter(|p| !required.contains(&p.name) && !p.provides.iter().any(|provided| required.contains(provided)))
+        .map(|p| p.name.clone())
+        .collect()
+}
+
+/// Dependencies removed along with the packages by `pacman -Rs`: installed as dependencies
+/// and no longer required once the packages, and the dependencies already found, are removed.
+pub fn removed_dependencies(installed: &HashMap<String, DatabasePackage>, removed: &HashSet<String>) -> HashSet<String> {
+    let mut gone = removed.clone();
+    loop {
+        let remaining: HashMap<String, DatabasePackage> = installed.iter().filter(|(name, _)| !gone.contains(*name)).map(|(name, p)| (name.clone(), p.clone())).collect();
+        let required = required(&remaining);
+        let depends: HashSet<&String> = installed.values().filter(|p| gone.contains(&p.name)).flat_map(|p| p.depends.iter()).collect();
+        let dependencies: Vec<String> = remaining
+            .values()
+            .filter(|p| !p.explicit && !required.contains(&p.name))
+            .filter(|p| depends.contains(&p.name) || p.provides.iter().any(|provided| depends.contains(provided)))
+            .map(|p| p.name.clone())
+            .collect();
+        if dependencies.is_empty() {
+            return gone.difference(removed).cloned().collect();
+        }
+        gone.extend(dependencies);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_local_nominal() {
+        // When
+        let packages = read_local(Path::new("tests/data")).unwrap();
+
+        // Then
+        assert_eq!(packages.len(), 7);
+        assert_eq!(packages["baobab"], DatabasePackage {
+            name: "baobab".to_string(),
+            version: "46.0-1".to_string(),
+            explicit: true,
+            groups: vec!["gnome".to_string()],
+            repository: None,
+            size: 2048,
+            depends: vec!["glibc".to_string(), "gtk4".to_string()],
+            optdepends: vec!["libopt".to_string()],
+            provides: vec![],
+        });
+        assert!(!packages["glibc"].explicit);
+        assert_eq!(packages["bash"].provides, vec!["sh".to_string()]);
+    }
+
+    #[test]
+    fn read_sync_nominal() {
+        // When
+        let database = SyncDatabase::read(Path::new("tests/data")).unwrap();
+
+        // Then
+        assert_eq!(database.packages.len(), 9);
+        let base = database.find("base").unwrap();
+        assert_eq!(base.repository, Some("core".to_string()));
+        assert_eq!(base.depends, vec!["glibc".to_string(), "sh".to_string()]);
+        let vim = database.find("vim").unwrap();
+        assert_eq!(vim.repository, Some("extra".to_string()));
+        assert!(database.find("paru-bin").is_none());
+        assert_eq!(database.groups()["gnome"], HashSet::from([
+            "baobab".to_string(),
+            "epiphany".to_string(),
+            "gdm".to_string(),
+        ]));
+    }
+
+    #[test]
+    fn resolve_nominal() {
+        // Given
+        let database = SyncDatabase::read(Path::new("tests/data")).unwrap();
+
+        // When / Then
+        assert_eq!(database.resolve("firefox"), Resolution::Package("extra".to_string()));
+        assert_eq!(database.resolve("gnome"), Resolution::Group);
+        assert_eq!(database.resolve("sh"), Resolution::Provider(vec!["bash".to_string()]));
+        assert_eq!(database.resolve("firefx"), Resolution::Unknown(vec!["firefox".to_string()]));
+        assert_eq!(database.resolve("gnom"), Resolution::Unknown(vec!["gnome".to_string(), "gdm".to_string()]));
+        assert_eq!(database.resolve("nothing-close"), Resolution::Unknown(vec![]));
+    }
+
+    #[test]
+    fn repositories_nominal() {
+        // Given
+        let database = SyncDatabase::read(Path::new("tests/data")).unwrap();
+
+        // When
+        let repositories = database.repositories();
+
+        // Then
+        assert_eq!(repositories.len(), 9);
+        assert_eq!(repositories.get("linux"), Some(&"core".to_string()));
+        assert_eq!(repositories.get("firefox"), Some(&"extra".to_string()));
+    }
+
+    #[test]
+    fn orphans_nominal() {
+        // Given
+        let packages = read_local(Path::new("tests/data")).unwrap();
+
+        // When
+        let orphans = orphans(&packages);
+
+        // Then
+        assert_eq!(orphans, HashSet::from(["libunused".to_string()]));
+    }
+
+    #[test]
+    fn removed_dependencies_nominal() {
+        // Given
+        let packages = read_local(Path::new("tests/data")).unwrap();
+
+        // When
+        let removed = removed_dependencies(&packages, &HashSet::from(["base".to_string()]));
+
+        // Then
+        assert_eq!(removed, HashSet::from(["bash".to_string()]));
+    }
+
+    #[test]
+    fn required_nominal() {
+        // Given
+        let packages = read_local(Path::new("tests/data")).unwrap();
+
+        // When
+        let required = required(&packages);
+
+        // Then
+        assert_eq!(required, HashSet::from(["glibc".to_string(), "bash".to_string()]));
+    }
+}
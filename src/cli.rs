@@ -0,0 +1,754 @@
+/*
+ *  cli.rs
+ *
+ *  Copyright (c) 2024-2024 Cédric ROYER <cedric dot royer at zaclys dot net>
+ *
+ *  This program is free software; you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation; either version 2 of the License, or
+ *  (at your option) any later version.
+ *
+ *  This program is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::backend::{self, flatpak::Flatpak, pacman::{self, conf, database, Pacman, RemovalStrategy}, Backend, BackendError};
+use crate::configuration::reader::{self, ConfigReaderError};
+use crate::configuration::condition::Facts;
+use crate::configuration::protection::Protection;
+use crate::configuration::selection::Selection;
+use crate::configuration::writer;
+use crate::engine::compute_actions::{Actions, PackageManager, PackageOrGroup};
+use crate::engine::reference::{Origin, Reference};
+use crate::history::{self, Entry, History, HistoryError};
+use clap::{Parser, Subcommand, ValueEnum};
+use std::collections::{BTreeSet, HashSet};
+use std::fmt::Display;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::str::FromStr;
+
+/// Synchronize the installed packages with the packages listed in the configuration.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Cli {
+    /// Configuration directory, target files are read from its `target` sub directory
+    #[arg(long, global = true, default_value = "/etc/pacsync.d")]
+    pub config_dir: PathBuf,
+
+    /// Host name selecting the `hosts/<hostname>` directory, the system one by default
+    #[arg(long, global = true)]
+    pub hostname: Option<String>,
+
+    /// Profile selecting the `profiles/<profile>` directory, can be repeated.
+    /// The profiles listed in the `active-profiles` file are used by default
+    #[arg(long = "profile", global = true)]
+    pub profiles: Vec<String>,
+
+    /// Never remove packages, only install the missing ones
+    #[arg(long, global = true)]
+    pub no_remove: bool,
+
+    /// Most packages removed at once, a number or a percentage of the explicitly installed packages (`20%`)
+    #[arg(long, global = true, default_value = "25%")]
+    pub max_remove: RemovalLimit,
+
+    /// Apply even when more packages than `--max-remove` would be removed
+    #[arg(long, global = true)]
+    pub allow_mass_removal: bool,
+
+    /// Remove the protected packages too: base, linux*, pacman, sudo, the bootloaders, the running kernel,
+    /// pacsync and the packages listed in the `protected` file
+    #[arg(long, global = true)]
+    pub force: bool,
+
+    /// AUR helper used to install `aur/` packages, run as the invoking user
+    #[arg(long, global = true, default_value = "paru")]
+    pub aur_helper: String,
+
+    /// How pacman removes packages
+    #[arg(long, global = true, value_enum, default_value_t = RemovalStrategy::Remove)]
+    pub removal: RemovalStrategy,
+
+    /// Remove all orphan packages once the actions are applied
+    #[arg(long, global = true)]
+    pub sweep_orphans: bool,
+
+    /// Simulate the transaction instead of applying it, without root privileges
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+
+    /// Pacman database directory
+    #[arg(long, global = true, default_value = pacman::DEFAULT_DB_PATH)]
+    pub dbpath: PathBuf,
+
+    /// Pacman configuration file, its `IgnorePkg` line holding packages back is maintained by pacsync
+    #[arg(long, global = true, default_value = conf::DEFAULT_PACMAN_CONF)]
+    pub pacman_conf: PathBuf,
+
+    /// Directory of the history of the applied runs [default: $XDG_STATE_HOME/pacsync/history]
+    #[arg(long, global = true)]
+    pub history_dir: Option<PathBuf>,
+
+    /// Install the versions satisfying the declared constraints from the package cache
+    /// and the archives, instead of only reporting them
+    #[arg(long, global = true)]
+    pub enforce_versions: bool,
+
+    /// Directory of package files searched after the package cache, can be repeated
+    #[arg(long = "archive", global = true, requires = "enforce_versions")]
+    pub archives: Vec<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Show the actions needed to synchronize the system
+    Plan {
+        /// Output format, json and toml describe each action with its reason and commands
+        #[arg(long, value_enum, default_value_t = Format::Text)]
+        format: Format,
+    },
+    /// Apply the actions needed to synchronize the system (default)
+    Apply {
+        /// Apply without asking for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Print the commands that would be run to synchronize the system
+    Print,
+    /// Exit with a failure status if the system is not synchronized
+    Check,
+    /// Remove the orphan packages, installed as dependencies and no longer required
+    Sweep,
+    /// Show the configuration files requiring a package
+    Why {
+        /// Package name
+        name: String,
+    },
+    /// List the applied runs recorded in the history
+    History,
+    /// Undo a run of the history: installed packages are removed, removed packages are installed again
+    /// at their previous version from the package cache and the archives
+    Rollback {
+        /// Run identifier, as listed by `pacsync history`
+        id: u32,
+        /// Apply without asking for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Export the explicitly installed packages in the configuration format
+    Export {
+        /// Write target files in this directory instead of printing them
+        #[arg(long)]
+        output_dir: Option<PathBuf>,
+        /// Write one file per repository (core, extra, multilib, foreign...)
+        #[arg(long)]
+        split_by_repository: bool,
+    },
+}
+
+/// How the plan is printed.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, ValueEnum)]
+pub enum Format {
+    Text,
+    Json,
+    Toml,
+}
+
+/// Most packages removed by a single run, above it the configuration is probably broken.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum RemovalLimit {
+    Count(usize),
+    /// percentage of the explicitly installed packages
+    Percent(usize),
+}
+
+impl RemovalLimit {
+    pub fn is_exceeded(&self, removed: usize, explicit: usize) -> bool {
+        match self {
+            RemovalLimit::Count(count) => removed > *count,
+            RemovalLimit::Percent(percent) => removed * 100 > percent * explicit,
+        }
+    }
+}
+
+impl FromStr for RemovalLimit {
+    type Err = String;
+
+    fn from_str(raw: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = |_| format!("expected a number of packages or a percentage: {}", raw);
+        match raw.strip_suffix('%') {
+            Some(percent) => Ok(RemovalLimit::Percent(percent.parse().map_err(invalid)?)),
+            None => Ok(RemovalLimit::Count(raw.parse().map_err(invalid)?)),
+        }
+    }
+}
+
+impl Display for RemovalLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RemovalLimit::Count(count) => write!(f, "{count} packages"),
+            RemovalLimit::Percent(percent) => write!(f, "{percent}%"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Configuration(ConfigReaderError),
+    Backend(BackendError),
+    Io(io::Error),
+    /// declared packages unknown by their package manager
+    Validation(Vec<String>),
+    /// protected packages removed along with the deleted ones
+    ProtectedRemovals(Vec<String>),
+    MissingArchives(Vec<String>),
+    Serialization(String),
+    /// more removals than allowed, with hints about the likely cause
+    TooManyRemovals(String, Vec<String>),
+    History(HistoryError),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+pub fn run(cli: Cli) -> Result<ExitCode> {
+    let backends = backends(&cli);
+    match cli.command {
+        Some(Command::Plan { format }) => {
+            let (reference, actions) = compute(&cli, &backends)?;
+            match format {
+                Format::Text => show_plan(&backends, &actions)?,
+                Format::Json => {
+                    let plan = backend::plan(&backends, &actions, &reference);
+                    println!("{}", serde_json::to_string_pretty(&plan).map_err(|err| Error::Serialization(err.to_string()))?);
+                }
+                Format::Toml => {
+                    let plan = backend::plan(&backends, &actions, &reference);
+                    print!("{}", toml::to_string(&plan).map_err(|err| Error::Serialization(err.to_string()))?);
+                }
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        None => apply(&cli, &backends, false),
+        Some(Command::Apply { yes }) => apply(&cli, &backends, yes),
+        Some(Command::Print) => {
+            backend::print_actions(&backends, &compute(&cli, &backends)?.1);
+            backend::print_sweep(&backends)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Command::Check) => {
+            let (_, actions) = compute(&cli, &backends)?;
+            println!("{}", actions);
+            if actions.is_empty() {
+                Ok(ExitCode::SUCCESS)
+            } else {
+                Ok(ExitCode::FAILURE)
+            }
+        }
+        Some(Command::Sweep) if cli.dry_run => {
+            backend::print_sweep(&backends)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Command::Sweep) => {
+            check_protected_removals(&cli, &backends, &Actions::default())?;
+            if backend::removed_along(&backends, &Actions::default())?.is_empty() {
+                return Ok(ExitCode::SUCCESS);
+            }
+            // the configuration is not needed to sweep, it only identifies the run
+            let files = read_reference(&cli).map(|reference| reference.files).unwrap_or_default();
+            apply_and_record(&cli, &backends, &Actions::default(), &files)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Command::Why { ref name }) => why(&cli, name),
+        Some(Command::History) => {
+            for entry in history(&cli).entries()? {
+                println!("{}", entry);
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Command::Rollback { id, yes }) => rollback(&cli, &backends, id, yes),
+        Some(Command::Export { ref output_dir, split_by_repository }) => {
+            export(&cli, output_dir.as_deref(), split_by_repository)
+        }
+    }
+}
+
+fn backends(cli: &Cli) -> Vec<Box<dyn Backend>> {
+    let sweep_orphans = cli.sweep_orphans || matches!(cli.command, Some(Command::Sweep));
+    let pacman = Pacman::new(cli.aur_helper.clone())
+        .with_removal(cli.removal)
+        .with_orphan_sweep(sweep_orphans)
+        .with_db_path(cli.dbpath.clone())
+        .with_pacman_conf(cli.pacman_conf.clone());
+    // a rollback installs the previous versions from the package cache
+    let pacman = if cli.enforce_versions || matches!(cli.command, Some(Command::Rollback { .. })) {
+        let archives = std::iter::once(PathBuf::from(pacman::DEFAULT_CACHE_DIR)).chain(cli.archives.iter().cloned()).collect();
+        pacman.with_archives(archives)
+    } else {
+        pacman
+    };
+    vec![Box::new(pacman), Box::new(Flatpak)]
+}
+
+fn history(cli: &Cli) -> History {
+    History::new(cli.history_dir.clone().unwrap_or_else(history::default_dir))
+}
+
+/// Read the configuration fragments selected for this machine, with the conditions evaluated
+/// against its facts.
+fn read_reference(cli: &Cli) -> Result<Reference> {
+    let selection = Selection::new(&cli.config_dir, cli.hostname.clone(), cli.profiles.clone())?;
+    let facts = Facts::gather(selection.hostname.clone());
+    Ok(reader::read_all(&selection.directories(&cli.config_dir)?, &facts)?)
+}
+
+fn compute(cli: &Cli, backends: &[Box<dyn Backend>]) -> Result<(Reference, Actions)> {
+    let reference = read_reference(cli)?;
+    for duplicate in reference.duplicates() {
+        eprintln!("warning: {}", duplicate);
+    }
+    let declared = reference.declared();
+    let problems = backend::validate(backends, &declared)?;
+    if !problems.is_empty() {
+        return Err(Error::Validation(problems));
+    }
+    let mut actions = backend::compute_actions(backends, &declared, &reference.excluded(), &reference.held())?;
+    for update in backend::held_updates(backends, &reference.held())? {
+        eprintln!("warning: {}", update);
+    }
+    if !cli.enforce_versions {
+        for unsatisfied in actions.take_unsatisfied() {
+            eprintln!("warning: version constraint not satisfied: {}", unsatisfied);
+        }
+    }
+    if cli.no_remove {
+        actions.to_delete.clear();
+    }
+    protect(cli, &mut actions)?;
+    Ok((reference, actions))
+}
+
+/// Keep the protected packages, unless forced.
+fn protect(cli: &Cli, actions: &mut Actions) -> Result<()> {
+    if cli.force {
+        return Ok(());
+    }
+    let protection = Protection::load(&cli.config_dir)?;
+    let mut protected: Vec<PackageOrGroup> = actions.to_delete.iter().filter(|p| protection.is_protected(&p.name)).cloned().collect();
+    protected.sort_by(|a, b| a.name.cmp(&b.name));
+    for p_or_g in protected {
+        eprintln!("warning: {} is protected and will not be removed, use --force to remove it", p_or_g.name);
+        actions.to_delete.remove(&p_or_g);
+    }
+    Ok(())
+}
+
+/// Refuse to remove protected packages along with the deleted ones, unless forced: the package
+/// manager chooses these dependencies and orphans, they cannot be kept like the deleted packages.
+fn check_protected_removals(cli: &Cli, backends: &[Box<dyn Backend>], actions: &Actions) -> Result<()> {
+    if cli.force {
+        return Ok(());
+    }
+    let protection = Protection::load(&cli.config_dir)?;
+    let protected: Vec<String> = backend::removed_along(backends, actions)?
+        .into_iter()
+        .map(|p_or_g| p_or_g.name)
+        .filter(|name| protection.is_protected(name))
+        .collect();
+    if protected.is_empty() {
+        return Ok(());
+    }
+    Err(Error::ProtectedRemovals(protected))
+}
+
+/// Print the declarations requiring the package: directly, through a group or as a dependency
+/// of a declared package, and the exclusions overriding them.
+fn why(cli: &Cli, name: &str) -> Result<ExitCode> {
+    let reference = read_reference(cli)?;
+    let print_origins = |origins: &[Origin]| {
+        for origin in origins {
+            println!("\t- {}", origin);
+        }
+    };
+    let mut required = false;
+    let exclusions: Vec<Origin> = reference.exclusion_origins(name).into_iter().cloned().collect();
+    if !exclusions.is_empty() {
+        println!("{} is excluded in:", name);
+        print_origins(&exclusions);
+        required = true;
+    }
+    for (p_or_g, origins) in reference.find(name) {
+        println!("{} ({}) is declared in:", p_or_g.name, p_or_g.manager);
+        print_origins(origins);
+        if let Some(annotation) = reference.annotation(p_or_g) {
+            for (label, value) in [("owner", &annotation.owner), ("comment", &annotation.comment)] {
+                if let Some(value) = value {
+                    println!("\t{}: {}", label, value);
+                }
+            }
+        }
+        required = true;
+    }
+
+    let installed = database::read_local(&cli.dbpath)?;
+    let groups = match installed.get(name) {
+        Some(package) => package.groups.clone(),
+        None => database::SyncDatabase::read(&cli.dbpath)?.find(name).map(|p| p.groups.clone()).unwrap_or_default(),
+    };
+    for group in groups {
+        for (p_or_g, origins) in reference.find(&group).into_iter().filter(|(p_or_g, _)| p_or_g.manager == PackageManager::PACMAN) {
+            println!("{} is a member of the group {} declared in:", name, p_or_g.name);
+            print_origins(origins);
+            required = true;
+        }
+    }
+
+    let mut dependents: Vec<&String> = installed.values().filter(|p| p.depends.iter().any(|d| d == name)).map(|p| &p.name).collect();
+    dependents.sort();
+    for dependent in dependents {
+        for (_, origins) in reference.find(dependent) {
+            println!("{} is required by {} declared in:", name, dependent);
+            print_origins(origins);
+            required = true;
+        }
+    }
+
+    if required {
+        Ok(ExitCode::SUCCESS)
+    } else {
+        println!("{} is not required by the configuration", name);
+        Ok(ExitCode::FAILURE)
+    }
+}
+
+fn export(cli: &Cli, output_dir: Option<&Path>, split_by_repository: bool) -> Result<ExitCode> {
+    let sync = database::SyncDatabase::read(&cli.dbpath)?;
+    let files = writer::export(
+        &pacman::get_explicit_installed_packages(&cli.dbpath)?,
+        &sync.groups(),
+        &sync.repositories(),
+        split_by_repository,
+    );
+    match output_dir {
+        Some(dir) => writer::write(dir, &files)?,
+        None => {
+            for (name, lines) in files {
+                println!("# {}", name);
+                for line in lines {
+                    println!("{}", line);
+                }
+                println!();
+            }
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Print the actions, followed by their effects on the dependencies.
+fn show_plan(backends: &[Box<dyn Backend>], actions: &Actions) -> Result<()> {
+    println!("# actions to be done\n{}", actions);
+    if !actions.is_empty() {
+        let effects = backend::compute_effects(backends, actions)?;
+        if !effects.is_empty() {
+            println!("# effects\n{}", effects);
+        }
+    }
+    Ok(())
+}
+
+fn apply(cli: &Cli, backends: &[Box<dyn Backend>], yes: bool) -> Result<ExitCode> {
+    let (reference, actions) = compute(cli, backends)?;
+    show_plan(backends, &actions)?;
+    if actions.is_empty() {
+        return Ok(ExitCode::SUCCESS);
+    }
+    if cli.dry_run {
+        println!("# dry run\n{}", backend::simulate(backends, &actions)?);
+        backend::print_sweep(backends)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    check_protected_removals(cli, backends, &actions)?;
+    if !cli.allow_mass_removal && !actions.to_delete.is_empty() {
+        check_removals(cli, Some(&reference), &actions, backend::count_explicit(backends)?)?;
+    }
+    if !yes {
+        if let Some(code) = confirm(backends, &actions)? {
+            return Ok(code);
+        }
+    }
+    apply_and_record(cli, backends, &actions, &reference.files)?;
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Ask before applying the actions, the exit code is given when they must not be applied.
+fn confirm(backends: &[Box<dyn Backend>], actions: &Actions) -> Result<Option<ExitCode>> {
+    println!("apply/print/no abort [y/p/n] ?");
+    let answer = get_answer()?;
+    if answer == "p\n" {
+        backend::print_actions(backends, actions);
+        backend::print_sweep(backends)?;
+        return Ok(Some(ExitCode::SUCCESS));
+    } else if answer != "y\n" {
+        println!("Abort");
+        return Ok(Some(ExitCode::FAILURE));
+    }
+    Ok(None)
+}
+
+/// Apply the actions and record the run in the history, even when it fails.
+/// A run that cannot be recorded is only reported.
+fn apply_and_record(cli: &Cli, backends: &[Box<dyn Backend>], actions: &Actions, files: &BTreeSet<PathBuf>) -> Result<()> {
+    let versions = backend::installed_versions(backends)?;
+    let removed_along = backend::removed_along(backends, actions)?;
+    let result = backend::apply_actions(backends, actions).and_then(|_| backend::sweep(backends));
+    let config_hash = history::config_hash(files).unwrap_or_default();
+    let entry = Entry::new(config_hash, actions, &removed_along, &versions, &result);
+    match history(cli).record(entry) {
+        Ok(id) => println!("recorded as run {}", id),
+        Err(err) => eprintln!("warning: cannot record the run: {}", err),
+    }
+    Ok(result?)
+}
+
+/// Apply the actions undoing a run of the history.
+fn rollback(cli: &Cli, backends: &[Box<dyn Backend>], id: u32, yes: bool) -> Result<ExitCode> {
+    let entry = history(cli).get(id)?;
+    let mut held = HashSet::new();
+    for backend in backends {
+        held.extend(backend.get_held_packages()?.into_iter().map(|p| PackageOrGroup::new(p.name, p.manager)));
+    }
+    let mut actions = entry.inverse(held);
+    protect(cli, &mut actions)?;
+    println!("# actions undoing run {}\n{}", id, actions);
+    if actions.is_empty() {
+        return Ok(ExitCode::SUCCESS);
+    }
+    // without package file, the latest version would be installed instead of the previous one
+    let missing = backend::missing_archives(backends, &actions.to_add);
+    if !missing.is_empty() {
+        return Err(Error::MissingArchives(missing));
+    }
+    if cli.dry_run {
+        backend::print_actions(backends, &actions);
+        return Ok(ExitCode::SUCCESS);
+    }
+    check_protected_removals(cli, backends, &actions)?;
+    if !cli.allow_mass_removal && !actions.to_delete.is_empty() {
+        check_removals(cli, None, &actions, backend::count_explicit(backends)?)?;
+    }
+    if !yes {
+        if let Some(code) = confirm(backends, &actions)? {
+            return Ok(code);
+        }
+    }
+    // the configuration may be the reason of the rollback, it is only hashed when readable
+    let files = read_reference(cli).map(|reference| reference.files).unwrap_or_default();
+    apply_and_record(cli, backends, &actions, &files)?;
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Refuse to remove more packages than the limit: an empty or unreadable configuration
+/// looks like a request to remove everything. Without configuration, as for a rollback,
+/// there is no hint about it.
+fn check_removals(cli: &Cli, reference: Option<&Reference>, actions: &Actions, explicit: usize) -> Result<()> {
+    let removed = actions.to_delete.len();
+    if !cli.max_remove.is_exceeded(removed, explicit) {
+        return Ok(());
+    }
+    let mut hints = Vec::new();
+    if let Some(reference) = reference {
+        let declared = reference.declared().len();
+        if declared == 0 {
+            hints.push(format!("no package is declared: are the files of {} empty or commented out?", cli.config_dir.display()));
+        } else if removed > declared {
+            hints.push(format!("more packages would be removed than the {} declared: is a target file missing or unreadable?", declared));
+        }
+        hints.push("check the host name and the profiles selecting the configuration directories".to_string());
+    }
+    hints.push("use --allow-mass-removal to apply anyway, or --max-remove to change the limit".to_string());
+    let message = format!("refusing to remove {} of the {} explicitly installed packages, the limit is {}:", removed, explicit, cli.max_remove);
+    Err(Error::TooManyRemovals(message, hints))
+}
+
+fn get_answer() -> io::Result<String> {
+    let mut buffer = String::new();
+
+    io::stdin().read_line(&mut buffer)?;
+
+    Ok(buffer)
+}
+
+impl From<ConfigReaderError> for Error {
+    fn from(err: ConfigReaderError) -> Self {
+        Self::Configuration(err)
+    }
+}
+
+impl From<BackendError> for Error {
+    fn from(err: BackendError) -> Self {
+        Self::Backend(err)
+    }
+}
+
+impl From<HistoryError> for Error {
+    fn from(err: HistoryError) -> Self {
+        Self::History(err)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Configuration(err) => write!(f, "cannot read configuration: {err}"),
+            Error::Backend(err) => write!(f, "{err}"),
+            Error::Io(err) => write!(f, "{err}"),
+            Error::Validation(problems) => write!(f, "invalid configuration:\n - {}", problems.join("\n - ")),
+            Error::ProtectedRemovals(names) => write!(f, "protected packages would be removed along with the other packages: {}\n\
+                use --force to remove them, or keep them with --removal remove and without --sweep-orphans", names.join(", ")),
+            Error::MissingArchives(missing) => write!(f, "cannot install the previous versions:\n - {}", missing.join("\n - ")),
+            Error::Serialization(err) => write!(f, "cannot serialize the plan: {err}"),
+            Error::TooManyRemovals(message, hints) => write!(f, "{message}\n - {}", hints.join("\n - ")),
+            Error::History(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_command() {
+        // When
+        let cli = Cli::try_parse_from(["pacsync"]).unwrap();
+
+        // Then
+        assert!(cli.command.is_none());
+        assert_eq!(cli.config_dir, PathBuf::from("/etc/pacsync.d"));
+        assert!(!cli.no_remove);
+        assert!(!cli.dry_run);
+        assert!(!cli.force);
+    }
+
+    #[test]
+    fn apply_with_flags() {
+        // When
+        let cli = Cli::try_parse_from(["pacsync", "apply", "--yes", "--no-remove", "--dry-run", "--config-dir", "/tmp/conf"]).unwrap();
+
+        // Then
+        assert!(matches!(cli.command, Some(Command::Apply { yes: true })));
+        assert_eq!(cli.config_dir, PathBuf::from("/tmp/conf"));
+        assert!(cli.no_remove);
+        assert!(cli.dry_run);
+    }
+
+    #[test]
+    fn removal_strategy() {
+        // When
+        let default = Cli::try_parse_from(["pacsync", "plan"]).unwrap();
+        let recursive = Cli::try_parse_from(["pacsync", "plan", "--removal", "recursive-nosave"]).unwrap();
+
+        // Then
+        assert_eq!(default.removal, RemovalStrategy::Remove);
+        assert_eq!(recursive.removal, RemovalStrategy::RecursiveNosave);
+    }
+
+    #[test]
+    fn plan_format() {
+        // When
+        let default = Cli::try_parse_from(["pacsync", "plan"]).unwrap();
+        let json = Cli::try_parse_from(["pacsync", "plan", "--format", "json"]).unwrap();
+
+        // Then
+        assert!(matches!(default.command, Some(Command::Plan { format: Format::Text })));
+        assert!(matches!(json.command, Some(Command::Plan { format: Format::Json })));
+    }
+
+    #[test]
+    fn repeated_profiles() {
+        // When
+        let cli = Cli::try_parse_from(["pacsync", "plan", "--profile", "dev", "--profile", "office", "--hostname", "laptop"]).unwrap();
+
+        // Then
+        assert_eq!(cli.profiles, vec!["dev".to_string(), "office".to_string()]);
+        assert_eq!(cli.hostname, Some("laptop".to_string()));
+    }
+
+    #[test]
+    fn removal_limit() {
+        // When
+        let cli = Cli::try_parse_from(["pacsync"]).unwrap();
+        let count = Cli::try_parse_from(["pacsync", "--max-remove", "10"]).unwrap();
+
+        // Then
+        assert_eq!(cli.max_remove, RemovalLimit::Percent(25));
+        assert_eq!(count.max_remove, RemovalLimit::Count(10));
+        assert!(Cli::try_parse_from(["pacsync", "--max-remove", "many"]).is_err());
+        assert!(cli.max_remove.is_exceeded(26, 100));
+        assert!(!cli.max_remove.is_exceeded(25, 100));
+        assert!(count.max_remove.is_exceeded(11, 1000));
+    }
+
+    #[test]
+    fn removals_refused_for_empty_configuration() {
+        // Given
+        let cli = Cli::try_parse_from(["pacsync", "--config-dir", "/tmp/empty"]).unwrap();
+        let actions = Actions {
+            to_delete: HashSet::from([
+                PackageOrGroup::new("vim".to_string(), PackageManager::PACMAN),
+                PackageOrGroup::new("gnome-shell".to_string(), PackageManager::PACMAN),
+            ]),
+            ..Default::default()
+        };
+
+        // When
+        let refused = check_removals(&cli, Some(&Reference::default()), &actions, 4);
+        let allowed = check_removals(&cli, Some(&Reference::default()), &actions, 40);
+        let rollback = check_removals(&cli, None, &actions, 4);
+
+        // Then
+        assert!(matches!(refused, Err(Error::TooManyRemovals(message, hints))
+            if message == "refusing to remove 2 of the 4 explicitly installed packages, the limit is 25%:"
+                && hints[0] == "no package is declared: are the files of /tmp/empty empty or commented out?"));
+        assert!(allowed.is_ok());
+        assert!(matches!(rollback, Err(Error::TooManyRemovals(_, hints))
+            if hints == ["use --allow-mass-removal to apply anyway, or --max-remove to change the limit"]));
+    }
+
+    #[test]
+    fn rollback_command() {
+        // When
+        let cli = Cli::try_parse_from(["pacsync", "rollback", "12", "--yes", "--history-dir", "/tmp/history"]).unwrap();
+
+        // Then
+        assert!(matches!(cli.command, Some(Command::Rollback { id: 12, yes: true })));
+        assert_eq!(cli.history_dir, Some(PathBuf::from("/tmp/history")));
+    }
+
+    #[test]
+    fn archives_require_enforcement() {
+        // When
+        let cli = Cli::try_parse_from(["pacsync", "apply", "--enforce-versions", "--archive", "/srv/archive"]).unwrap();
+        let without_enforcement = Cli::try_parse_from(["pacsync", "apply", "--archive", "/srv/archive"]);
+
+        // Then
+        assert!(cli.enforce_versions);
+        assert_eq!(cli.archives, vec![PathBuf::from("/srv/archive")]);
+        assert!(without_enforcement.is_err());
+    }
+}
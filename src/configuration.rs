@@ -17,4 +17,8 @@
  *  along with this program.  If not, see <http://www.gnu.org/licenses/>.
  */
 
+pub mod condition;
+pub mod protection;
 pub mod reader;
+pub mod selection;
+pub mod writer;
@@ -0,0 +1,165 @@
+/*
+ *  configuration/condition.rs
+ *
+ *  Copyright (c) 2024-2024 Cédric ROYER <cedric dot royer at zaclys dot net>
+ *
+ *  This program is free software; you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation; either version 2 of the License, or
+ *  (at your option) any later version.
+ *
+ *  This program is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+use glob::Pattern;
+
+/// Facts about the machine, the conditions of the configuration files are evaluated against them.
+/// They are gathered once, tests build them by hand.
+#[derive(PartialEq, Eq, Debug, Default, Clone)]
+pub struct Facts {
+    /// CPU vendor: amd, intel or the raw vendor id
+    pub cpu: Option<String>,
+    /// vendor of every display controller: amd, intel, nvidia or the raw PCI vendor id
+    pub gpus: Vec<String>,
+    pub hostname: Option<String>,
+    /// running kernel release, `6.6.30-1-lts` for instance
+    pub kernel: Option<String>,
+    pub arch: String,
+    pub env: HashMap<String, String>,
+}
+
+impl Facts {
+    /// Facts of the running machine, the hostname being chosen by the caller.
+    pub fn gather(hostname: Option<String>) -> Self {
+        Facts {
+            cpu: fs::read_to_string("/proc/cpuinfo").ok().and_then(|cpuinfo| parse_cpu_vendor(&cpuinfo)),
+            gpus: read_gpu_vendors(Path::new("/sys/bus/pci/devices")),
+            hostname,
+            kernel: fs::read_to_string("/proc/sys/kernel/osrelease").ok().map(|release| release.trim().to_string()),
+            arch: env::consts::ARCH.to_string(),
+            env: env::vars().collect(),
+        }
+    }
+
+    /// Evaluate space separated conditions, all of them must hold: `cpu=amd gpu!=nvidia env.DESKTOP=*`.
+    /// Values are glob patterns.
+    pub fn evaluate(&self, conditions: &str) -> Result<bool, String> {
+        let mut result = true;
+        for condition in conditions.split_whitespace() {
+            let (key, value, negated) = match condition.split_once("!=") {
+                Some((key, value)) => (key, value, true),
+                None => match condition.split_once('=') {
+                    Some((key, value)) => (key, value, false),
+                    None => return Err(format!("condition without value: {}", condition)),
+                },
+            };
+            let pattern = Pattern::new(value).map_err(|err| format!("invalid value in {}: {}", condition, err))?;
+            let matches = self.values(key)?.iter().any(|fact| pattern.matches(fact));
+            result &= matches != negated;
+        }
+        Ok(result)
+    }
+
+    fn values(&self, key: &str) -> Result<Vec<&String>, String> {
+        match key {
+            "cpu" => Ok(self.cpu.iter().collect()),
+            "gpu" => Ok(self.gpus.iter().collect()),
+            "hostname" => Ok(self.hostname.iter().collect()),
+            "kernel" => Ok(self.kernel.iter().collect()),
+            "arch" => Ok(vec![&self.arch]),
+            _ => match key.strip_prefix("env.") {
+                Some(variable) => Ok(self.env.get(variable).into_iter().collect()),
+                None => Err(format!("unknown condition: {}, expected cpu, gpu, hostname, kernel, arch or env.<VARIABLE>", key)),
+            },
+        }
+    }
+}
+
+fn parse_cpu_vendor(cpuinfo: &str) -> Option<String> {
+    let vendor = cpuinfo
+        .lines()
+        .find(|line| line.starts_with("vendor_id"))
+        .and_then(|line| line.split_once(':'))
+        .map(|(_, vendor)| vendor.trim())?;
+    Some(match vendor {
+        "AuthenticAMD" => "amd".to_string(),
+        "GenuineIntel" => "intel".to_string(),
+        other => other.to_lowercase(),
+    })
+}
+
+/// Vendors of the display controllers (PCI class 0x03) found in sysfs.
+fn read_gpu_vendors(devices: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(devices) else {
+        return Vec::new();
+    };
+    let mut vendors = Vec::new();
+    for entry in entries.flatten() {
+        let read = |file: &str| fs::read_to_string(entry.path().join(file)).map(|content| content.trim().to_string());
+        if !read("class").is_ok_and(|class| class.starts_with("0x03")) {
+            continue;
+        }
+        if let Ok(vendor) = read("vendor") {
+            let vendor = match vendor.as_str() {
+                "0x1002" => "amd".to_string(),
+                "0x8086" => "intel".to_string(),
+                "0x10de" => "nvidia".to_string(),
+                _ => vendor,
+            };
+            if !vendors.contains(&vendor) {
+                vendors.push(vendor);
+            }
+        }
+    }
+    vendors.sort();
+    vendors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn facts() -> Facts {
+        Facts {
+            cpu: Some("amd".to_string()),
+            gpus: vec!["intel".to_string(), "nvidia".to_string()],
+            hostname: Some("laptop".to_string()),
+            kernel: Some("6.6.30-1-lts".to_string()),
+            arch: "x86_64".to_string(),
+            env: HashMap::from([("DESKTOP".to_string(), "gnome".to_string())]),
+        }
+    }
+
+    #[test]
+    fn evaluate_conditions() {
+        // Given
+        let facts = facts();
+
+        // When / Then
+        assert_eq!(facts.evaluate("cpu=amd"), Ok(true));
+        assert_eq!(facts.evaluate("cpu=intel"), Ok(false));
+        assert_eq!(facts.evaluate("gpu=nvidia"), Ok(true));
+        assert_eq!(facts.evaluate("gpu!=nvidia"), Ok(false));
+        assert_eq!(facts.evaluate("kernel=*-lts arch=x86_64"), Ok(true));
+        assert_eq!(facts.evaluate("hostname=laptop env.DESKTOP=kde"), Ok(false));
+        assert_eq!(facts.evaluate("env.UNSET!=*"), Ok(true));
+        assert!(facts.evaluate("ram=16G").is_err());
+        assert!(facts.evaluate("cpu").is_err());
+    }
+
+    #[test]
+    fn cpu_vendor_from_cpuinfo() {
+        assert_eq!(parse_cpu_vendor("processor\t: 0\nvendor_id\t: GenuineIntel\n"), Some("intel".to_string()));
+        assert_eq!(parse_cpu_vendor("processor\t: 0\n"), None);
+    }
+}
@@ -0,0 +1,88 @@
+/*
+ *  configuration/protection.rs
+ *
+ *  Copyright (c) 2024-2024 Cédric ROYER <cedric dot royer at zaclys dot net>
+ *
+ *  This program is free software; you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation; either version 2 of the License, or
+ *  (at your option) any later version.
+ *
+ *  This program is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::configuration::reader::{self, ConfigReaderError};
+use glob::Pattern;
+use std::fs;
+use std::path::Path;
+
+/// File listing more protected packages, one name or glob pattern per line.
+pub const PROTECTED_FILE: &str = "protected";
+
+/// Packages needed to boot and to administrate the machine, and pacsync itself.
+const BUILTIN_PROTECTED: [&str; 13] = [
+    "base", "linux*", "pacman", "sudo", "pacsync",
+    // bootloaders, systemd-boot comes with systemd
+    "grub", "systemd", "refind", "syslinux", "limine", "efibootmgr", "shim", "os-prober",
+];
+
+/// Packages never removed without `--force`.
+#[derive(Debug)]
+pub struct Protection {
+    patterns: Vec<Pattern>,
+}
+
+impl Protection {
+    /// Built-in protected packages, the package of the running kernel and the ones listed
+    /// in the protected file of the configuration directory.
+    pub fn load(config_dir: &Path) -> Result<Protection, ConfigReaderError> {
+        let mut names: Vec<String> = BUILTIN_PROTECTED.iter().map(|name| name.to_string()).collect();
+        names.extend(running_kernel());
+        names.extend(reader::read_list(&config_dir.join(PROTECTED_FILE))?);
+        Protection::new(&names)
+    }
+
+    pub fn new(names: &[String]) -> Result<Protection, ConfigReaderError> {
+        let patterns = names
+            .iter()
+            .map(|name| Pattern::new(name).map_err(|err| ConfigReaderError::ParseError(format!("invalid protected package {}: {}", name, err))))
+            .collect::<Result<_, _>>()?;
+        Ok(Protection{patterns})
+    }
+
+    pub fn is_protected(&self, name: &str) -> bool {
+        self.patterns.iter().any(|pattern| pattern.matches(name))
+    }
+}
+
+/// Package of the running kernel, recorded in its modules directory.
+fn running_kernel() -> Option<String> {
+    let release = fs::read_to_string("/proc/sys/kernel/osrelease").ok()?;
+    let pkgbase = fs::read_to_string(Path::new("/usr/lib/modules").join(release.trim()).join("pkgbase")).ok()?;
+    Some(pkgbase.trim().to_string()).filter(|name| !name.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_and_configured() {
+        // When
+        let protection = Protection::load(Path::new("tests/data/config")).unwrap();
+
+        // Then
+        assert!(protection.is_protected("base"));
+        assert!(protection.is_protected("linux-lts"));
+        assert!(protection.is_protected("gdm"));
+        assert!(protection.is_protected("nvidia-utils"));
+        assert!(!protection.is_protected("base-devel"));
+        assert!(!protection.is_protected("vim"));
+    }
+}
@@ -17,64 +17,279 @@
  *  along with this program.  If not, see <http://www.gnu.org/licenses/>.
  */
 
-use crate::engine::compute_actions::{PackageOrGroup,PackageManager};
-use std::collections::HashSet;
+use crate::configuration::condition::Facts;
+use crate::engine::compute_actions::{InstallReason, PackageOrGroup, PackageManager};
+use crate::engine::reference::{Annotation, Origin, Reference};
+use crate::engine::version::{self, Constraint};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt::Display;
 use std::fs;
 use std::fs::File;
 use std::io::{self, BufRead};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug)]
 pub enum ConfigReaderError {
     Io(io::Error),
     ParseError(String),
+    /// profile without directory
+    UnknownProfile(String, PathBuf),
+    /// configuration directory which does not exist, reading nothing would remove every package
+    MissingDirectory(PathBuf),
 }
 
-pub fn read(dir: &Path) -> Result<HashSet<PackageOrGroup>, ConfigReaderError> {
-    let mut reference = HashSet::<PackageOrGroup>::new();
-    visit_dirs(dir, &mut reference)?;
-    Ok(reference)
+pub fn read(dir: &Path, facts: &Facts) -> Result<Reference, ConfigReaderError> {
+    read_all(&[dir.to_path_buf()], facts)
 }
 
-fn visit_dirs(dir: &Path, reference: &mut HashSet<PackageOrGroup>) -> Result<(), ConfigReaderError> {
-    if dir.is_dir() {
-        for entry in fs::read_dir(dir)? {
-            let entry = entry?;
-            let path = entry.path();
+/// Line including other files: `@include <path or glob>`, relative to the including file.
+const INCLUDE_DIRECTIVE: &str = "@include";
+
+/// Prefix subtracting a package or a group from the declared ones: `!epiphany`.
+const EXCLUDE_PREFIX: &str = "!";
+
+/// Prefixes declaring a package held back from upgrades: `hold/linux-lts`.
+const HOLD_PREFIXES: [&str; 2] = ["hold/", "ignore/"];
+
+/// Closes a conditional block opened by `[if <conditions>]` alone on its line.
+const END_DIRECTIVE: &str = "[end]";
+
+/// Merge the declarations of several directories, conditions are evaluated against the facts.
+/// Every directory must exist.
+pub fn read_all(dirs: &[PathBuf], facts: &Facts) -> Result<Reference, ConfigReaderError> {
+    let mut reader = Reader{reference: Reference::default(), including: Vec::new(), facts};
+    for dir in dirs {
+        if !dir.is_dir() {
+            return Err(ConfigReaderError::MissingDirectory(dir.clone()));
+        }
+        reader.visit_dirs(dir)?;
+    }
+    Ok(reader.reference)
+}
+
+struct Reader<'a> {
+    reference: Reference,
+    /// chain of files being read, to detect include cycles
+    including: Vec<PathBuf>,
+    facts: &'a Facts,
+}
+
+impl Reader<'_> {
+    fn visit_dirs(&mut self, dir: &Path) -> Result<(), ConfigReaderError> {
+        if dir.is_dir() {
+            // sorted, so that the origins of a package are always listed in the same order
+            let mut paths = fs::read_dir(dir)?.map(|entry| entry.map(|e| e.path())).collect::<io::Result<Vec<_>>>()?;
+            paths.sort();
+            for path in paths {
+                if path.is_dir() {
+                    self.visit_dirs(&path)?;
+                } else {
+                    self.insert_packages(&path)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn insert_packages(&mut self, filename: &Path) -> Result<(), ConfigReaderError> {
+        self.reference.files.insert(filename.to_path_buf());
+        if filename.extension().is_some_and(|extension| extension == "toml") {
+            return self.insert_toml_packages(filename);
+        }
+        self.including.push(fs::canonicalize(filename)?);
+        // opened conditional blocks, with their origin and whether their conditions hold
+        let mut blocks: Vec<(Origin, bool)> = Vec::new();
+        for (index, package) in read_packages(filename)?.enumerate() {
+            let package = package?;
+            let origin = Origin::new(filename.to_path_buf(), index + 1);
+
+            // ignore comments
+            if package.starts_with("#") {
+                continue;
+            }
+
+            // ignore empty line
+            if package.find(|c| !char::is_whitespace(c)).is_none() {
+                continue;
+            }
+
+            if package.trim() == END_DIRECTIVE {
+                if blocks.pop().is_none() {
+                    return Err(ConfigReaderError::ParseError(format!("{}: {} without [if", origin, END_DIRECTIVE)));
+                }
+                continue;
+            }
+            let mut package = package.as_str();
+            if let Some(condition) = package.trim().strip_prefix("[if ") {
+                let (conditions, guarded) = condition
+                    .split_once(']')
+                    .ok_or(ConfigReaderError::ParseError(format!("{}: missing ] in {}", origin, package)))?;
+                let holds = self.facts.evaluate(conditions).map_err(|err| ConfigReaderError::ParseError(format!("{}: {}", origin, err)))?;
+                if guarded.trim().is_empty() {
+                    blocks.push((origin, holds));
+                    continue;
+                }
+                if !holds {
+                    continue;
+                }
+                package = guarded.trim();
+            }
+            if blocks.iter().any(|(_, holds)| !holds) {
+                continue;
+            }
+
+            if let Some(pattern) = package.strip_prefix(INCLUDE_DIRECTIVE) {
+                // `@includes` is not the directive followed by a path
+                if !pattern.is_empty() && !pattern.starts_with(char::is_whitespace) {
+                    return Err(ConfigReaderError::ParseError(format!("{}: unknown directive {}", origin, package)));
+                }
+                self.include(pattern.trim(), &origin)?;
+                continue;
+            }
+            if let Some(excluded) = package.strip_prefix(EXCLUDE_PREFIX) {
+                let p_or_g = parse_line(excluded, &origin)?;
+                self.reference.exclude(p_or_g, origin);
+                continue;
+            }
+            if let Some(held) = HOLD_PREFIXES.iter().find_map(|prefix| package.strip_prefix(prefix)) {
+                let p_or_g = parse_line(held, &origin)?;
+                if p_or_g.manager == PackageManager::FLATPAK {
+                    return Err(ConfigReaderError::ParseError(format!("{}: flatpak applications cannot be held: {}", origin, held)));
+                }
+                self.reference.hold(p_or_g, origin);
+                continue;
+            }
+            let p_or_g = parse_line(package, &origin)?;
+            self.reference.insert(p_or_g, origin);
+        }
+        if let Some((origin, _)) = blocks.pop() {
+            return Err(ConfigReaderError::ParseError(format!("{}: [if without {}", origin, END_DIRECTIVE)));
+        }
+        self.including.pop();
+        Ok(())
+    }
+
+    /// Read a structured file, a table per package:
+    /// ```toml
+    /// [packages.firefox]
+    /// repository = "extra"
+    /// owner = "desktop team"
+    /// ```
+    fn insert_toml_packages(&mut self, filename: &Path) -> Result<(), ConfigReaderError> {
+        let content = fs::read_to_string(filename)?;
+        let file: TomlFile = toml::from_str(&content)
+            .map_err(|err| ConfigReaderError::ParseError(format!("{}: {}", filename.display(), err)))?;
+        for (name, package) in file.packages {
+            let line = content[..package.span().start].matches('\n').count() + 1;
+            let origin = Origin::new(filename.to_path_buf(), line);
+            let package = package.into_inner();
+            let manager = match &package.manager {
+                Some(manager) => parse_package_manager(manager, &origin)?,
+                None => PackageManager::PACMAN,
+            };
+            let mut p_or_g = PackageOrGroup::new(name, manager).with_reason(package.reason.unwrap_or_default());
+            if let Some(repository) = package.repository {
+                p_or_g = p_or_g.with_repository(repository);
+            }
+            if let Some(version) = package.version {
+                Constraint::parse(&version).map_err(|err| ConfigReaderError::ParseError(format!("{}: {}", origin, err)))?;
+                p_or_g = p_or_g.with_version(version);
+            }
+            if package.comment.is_some() || package.owner.is_some() {
+                self.reference.annotate(p_or_g.clone(), Annotation{comment: package.comment, owner: package.owner});
+            }
+            self.reference.insert(p_or_g, origin);
+        }
+        Ok(())
+    }
+
+    /// Read the files matching the pattern, directories are read like the target directory.
+    fn include(&mut self, pattern: &str, origin: &Origin) -> Result<(), ConfigReaderError> {
+        let error = |message: String| ConfigReaderError::ParseError(format!("{}: cannot include {}: {}", origin, pattern, message));
+        if pattern.is_empty() {
+            return Err(error("missing path".to_string()));
+        }
+        let base = origin.path.parent().unwrap_or(Path::new(""));
+        let full_pattern = base.join(pattern);
+        let paths = glob::glob(&full_pattern.to_string_lossy())
+            .map_err(|err| error(err.to_string()))?
+            .collect::<Result<Vec<PathBuf>, _>>()
+            .map_err(|err| error(err.to_string()))?;
+        if paths.is_empty() {
+            return Err(error("no such file".to_string()));
+        }
+        for path in paths {
+            // a directory holding a file being read includes it again
+            let canonical = fs::canonicalize(&path)?;
+            if self.including.iter().any(|file| file.starts_with(&canonical)) {
+                let chain: Vec<String> = self.including.iter().chain(std::iter::once(&canonical)).map(|p| p.display().to_string()).collect();
+                return Err(error(format!("include cycle {}", chain.join(" -> "))));
+            }
             if path.is_dir() {
-                visit_dirs(&path, reference)?;
+                self.visit_dirs(&path)?;
             } else {
-                insert_packages(&path, reference)?;
+                self.insert_packages(&path)?;
             }
         }
+        Ok(())
     }
-    Ok(())
 }
 
-fn insert_packages(filename: &Path, reference: &mut HashSet<PackageOrGroup>) -> Result<(), ConfigReaderError> {
-    for package in read_packages(filename)? {
-        let package = package?;
+/// `[manager/]name`, the manager being pacman by default, or `pacman/<repository>/name`,
+/// optionally followed by a version constraint: `linux-lts=6.6.*`.
+fn parse_line(line: &str, origin: &Origin) -> Result<PackageOrGroup, ConfigReaderError> {
+    let (package, constraint) = version::split_constraint(line);
+    let split: Vec<&str> = package.split("/").collect();
 
-        // ignore comments
-        if package.starts_with("#") {
-            continue;
+    let p_or_g = if split.len() == 1 {
+        PackageOrGroup::new(split[0].to_string(), PackageManager::PACMAN)
+    } else if split.len() == 2 {
+        parse_package(split[1], parse_package_manager(split[0], origin)?)
+    } else if split.len() == 3 && parse_package_manager(split[0], origin)? == PackageManager::PACMAN {
+        PackageOrGroup::new(split[2].to_string(), PackageManager::PACMAN).with_repository(split[1].to_string())
+    } else {
+        return Err(ConfigReaderError::ParseError(format!("{}: Too many / in line for {}", origin, line)));
+    };
+    match constraint {
+        Some(constraint) => {
+            Constraint::parse(constraint).map_err(|err| ConfigReaderError::ParseError(format!("{}: {}", origin, err)))?;
+            Ok(p_or_g.with_version(constraint.to_string()))
         }
+        None => Ok(p_or_g),
+    }
+}
 
-        // ignore empty line
-        if package.find(|c| !char::is_whitespace(c)).is_none() {
-            continue;
-        }
-        let split: Vec<&str> = package.split("/").collect();
-
-        if split.len() == 1 {
-            reference.insert(PackageOrGroup::new(split[0].to_string(), PackageManager::PACMAN));
-        } else if split.len() == 2 {
-            reference.insert(PackageOrGroup::new(split[1].to_string(), parse_package_manager(&split[0])?));
-        } else {
-            return Err(ConfigReaderError::ParseError(format!("Too many / in line for {}", package)));
-        }
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TomlFile {
+    #[serde(default)]
+    packages: BTreeMap<String, toml::Spanned<TomlPackage>>,
+}
+
+/// Options of a package in a structured file, all of them are optional.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TomlPackage {
+    manager: Option<String>,
+    repository: Option<String>,
+    reason: Option<InstallReason>,
+    version: Option<String>,
+    comment: Option<String>,
+    owner: Option<String>,
+}
+
+/// One name per line, comments and empty lines are ignored. No file means an empty list.
+pub fn read_list(path: &Path) -> Result<Vec<String>, ConfigReaderError> {
+    if !path.is_file() {
+        return Ok(Vec::new());
     }
-    Ok(())
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.to_string())
+        .collect())
 }
 
 fn read_packages(filename: &Path) -> io::Result<io::Lines<io::BufReader<File>>> {
@@ -82,23 +297,56 @@ fn read_packages(filename: &Path) -> io::Result<io::Lines<io::BufReader<File>>>
     Ok(io::BufReader::new(file).lines())
 }
 
-fn parse_package_manager(raw: &str) -> Result<PackageManager, ConfigReaderError> {
+fn parse_package(raw: &str, manager: PackageManager) -> PackageOrGroup {
+    // flatpak applications can be prefixed by their remote: flathub:org.gimp.GIMP
+    if manager == PackageManager::FLATPAK {
+        if let Some((remote, application)) = raw.split_once(':') {
+            return PackageOrGroup::new(application.to_string(), manager).with_repository(remote.to_string());
+        }
+    }
+    PackageOrGroup::new(raw.to_string(), manager)
+}
+
+fn parse_package_manager(raw: &str, origin: &Origin) -> Result<PackageManager, ConfigReaderError> {
     if raw == "local" {
-        return Ok(PackageManager::LOCAL);
+        Ok(PackageManager::LOCAL)
     } else if raw == "pacman" {
-        return Ok(PackageManager::PACMAN);
+        Ok(PackageManager::PACMAN)
+    } else if raw == "aur" {
+        Ok(PackageManager::AUR)
+    } else if raw == "flatpak" {
+        Ok(PackageManager::FLATPAK)
     } else {
-        return Err(ConfigReaderError::ParseError(format!("Unkown package manager: {}", raw)));
+        Err(ConfigReaderError::ParseError(format!("{}: Unkown package manager: {}", origin, raw)))
+    }
+}
+
+impl From<io::Error> for ConfigReaderError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl Display for ConfigReaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigReaderError::Io(err) => write!(f, "{err}"),
+            ConfigReaderError::ParseError(message) => write!(f, "{message}"),
+            ConfigReaderError::UnknownProfile(name, path) => write!(f, "unknown profile {name}: {} is not a directory", path.display()),
+            ConfigReaderError::MissingDirectory(path) => write!(f, "{} is not a directory", path.display()),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::{BTreeSet, HashSet};
+    use std::path::PathBuf;
     use super::*;
 
     #[test]
     fn nominal_case() {
-        let reference = read(Path::new("tests_config_dir")).unwrap();
+        let reference = read(Path::new("tests_config_dir"), &Facts::default()).unwrap().declared();
 
         let mut expected = HashSet::<PackageOrGroup>::new();
         expected.insert(PackageOrGroup::new("package_1".to_string(), PackageManager::PACMAN));
@@ -119,10 +367,224 @@ mod tests {
         expected.insert(PackageOrGroup::new("package_4".to_string(), PackageManager::PACMAN));
         assert_eq!(reference, expected);
     }
-}
 
-impl From<io::Error> for ConfigReaderError {
-    fn from(err: io::Error) -> Self {
-        Self::Io(err)
+    #[test]
+    fn aur_packages() {
+        let reference = read(Path::new("tests/data/aur"), &Facts::default()).unwrap().declared();
+
+        assert_eq!(reference, HashSet::from([PackageOrGroup::new("paru-bin".to_string(), PackageManager::AUR)]));
+    }
+
+    #[test]
+    fn flatpak_applications() {
+        let reference = read(Path::new("tests/data/flatpak"), &Facts::default()).unwrap().declared();
+
+        let mut expected = HashSet::<PackageOrGroup>::new();
+        expected.insert(PackageOrGroup::new("org.mozilla.firefox".to_string(), PackageManager::FLATPAK));
+        expected.insert(PackageOrGroup::new("org.gimp.GIMP".to_string(), PackageManager::FLATPAK));
+        assert_eq!(reference, expected);
+        let gimp = reference.iter().find(|p| p.name == "org.gimp.GIMP").unwrap();
+        assert_eq!(gimp.repository, Some("flathub".to_string()));
+    }
+
+    #[test]
+    fn pacman_repository() {
+        // When
+        let reference = read(Path::new("tests/data/repository"), &Facts::default()).unwrap().declared();
+
+        // Then
+        let firefox = reference.iter().find(|p| p.name == "firefox").unwrap();
+        assert_eq!(reference.len(), 1);
+        assert_eq!(firefox.manager, PackageManager::PACMAN);
+        assert_eq!(firefox.repository, Some("extra".to_string()));
+    }
+
+    #[test]
+    fn origins_of_declarations() {
+        // When
+        let reference = read(Path::new("tests_config_dir"), &Facts::default()).unwrap();
+
+        // Then
+        let package = PackageOrGroup::new("package_1_3".to_string(), PackageManager::PACMAN);
+        assert_eq!(reference.origins(&package), &[Origin::new(PathBuf::from("tests_config_dir/sub_dir1/second_level"), 5)]);
+        assert!(reference.duplicates().is_empty());
+    }
+
+    #[test]
+    fn read_all_merges_directories() {
+        // Given
+        let dirs = [PathBuf::from("tests/data/config/target"), PathBuf::from("tests/data/config/profiles/dev")];
+
+        // When
+        let reference = read_all(&dirs, &Facts::default()).unwrap();
+
+        // Then
+        let names: HashSet<String> = reference.declared().into_iter().map(|p| p.name).collect();
+        assert_eq!(names, HashSet::from(["vim".to_string(), "base".to_string(), "gcc".to_string(), "gdb".to_string()]));
+    }
+
+    #[test]
+    fn include_relative_and_glob() {
+        // When
+        let reference = read(Path::new("tests/data/include/target"), &Facts::default()).unwrap();
+
+        // Then
+        let names: HashSet<String> = reference.declared().into_iter().map(|p| p.name).collect();
+        assert_eq!(names, HashSet::from(["base".to_string(), "gcc".to_string(), "gdb".to_string(), "vim".to_string()]));
+        let gcc = PackageOrGroup::new("gcc".to_string(), PackageManager::PACMAN);
+        assert_eq!(reference.origins(&gcc), &[Origin::new(PathBuf::from("tests/data/include/target/../shared/dev-tools"), 1)]);
+    }
+
+    #[test]
+    fn include_errors_point_to_the_line() {
+        // When
+        let cycle = read(Path::new("tests/data/include/cycle"), &Facts::default());
+        let missing = read(Path::new("tests/data/include/missing"), &Facts::default());
+        let unknown = read(Path::new("tests/data/include/unknown"), &Facts::default());
+
+        // Then
+        assert!(matches!(cycle, Err(ConfigReaderError::ParseError(message)) if message.contains("include cycle")));
+        assert!(matches!(missing, Err(ConfigReaderError::ParseError(message))
+            if message.starts_with("tests/data/include/missing/main:2: cannot include nothing")));
+        assert!(matches!(unknown, Err(ConfigReaderError::ParseError(message))
+            if message == "tests/data/include/unknown/main:2: unknown directive @includes"));
+    }
+
+    #[test]
+    fn include_directory_cycle() {
+        // When
+        let cycle = read(Path::new("tests/data/include/directory_cycle"), &Facts::default());
+
+        // Then
+        assert!(matches!(cycle, Err(ConfigReaderError::ParseError(message))
+            if message.starts_with("tests/data/include/directory_cycle/main:2: cannot include .: include cycle")));
+    }
+
+    #[test]
+    fn conditional_lines_and_blocks() {
+        // Given
+        let facts = Facts {
+            cpu: Some("amd".to_string()),
+            gpus: vec!["nvidia".to_string()],
+            arch: "x86_64".to_string(),
+            ..Default::default()
+        };
+
+        // When
+        let reference = read(Path::new("tests/data/conditions/target"), &facts).unwrap();
+
+        // Then
+        let names: HashSet<String> = reference.declared().into_iter().map(|p| p.name).collect();
+        assert_eq!(names, HashSet::from([
+            "base".to_string(),
+            "amd-ucode".to_string(),
+            "nvidia".to_string(),
+            "nvidia-utils".to_string(),
+        ]));
+    }
+
+    #[test]
+    fn unclosed_block() {
+        // When
+        let reference = read(Path::new("tests/data/conditions/unclosed"), &Facts::default());
+
+        // Then
+        assert!(matches!(reference, Err(ConfigReaderError::ParseError(message))
+            if message == "tests/data/conditions/unclosed/main:2: [if without [end]"));
+    }
+
+    #[test]
+    fn exclusions() {
+        // When
+        let reference = read(Path::new("tests/data/exclusions"), &Facts::default()).unwrap();
+
+        // Then
+        let declared: HashSet<String> = reference.declared().into_iter().map(|p| p.name).collect();
+        let excluded: HashSet<String> = reference.excluded().into_iter().map(|p| p.name).collect();
+        assert_eq!(declared, HashSet::from(["gnome".to_string()]));
+        assert_eq!(excluded, HashSet::from(["epiphany".to_string(), "vim".to_string()]));
+    }
+
+    #[test]
+    fn structured_file() {
+        // When
+        let reference = read(Path::new("tests/data/structured"), &Facts::default()).unwrap();
+
+        // Then
+        let firefox = reference.packages.keys().find(|p| p.name == "firefox").unwrap();
+        assert_eq!(firefox.manager, PackageManager::PACMAN);
+        assert_eq!(firefox.repository, Some("extra".to_string()));
+        assert_eq!(firefox.version, Some(">=120".to_string()));
+        assert_eq!(reference.origins(firefox), &[Origin::new(PathBuf::from("tests/data/structured/desktop.toml"), 3)]);
+        assert_eq!(reference.annotation(firefox), Some(&Annotation{comment: None, owner: Some("desktop team".to_string())}));
+        let python = reference.packages.keys().find(|p| p.name == "python").unwrap();
+        assert_eq!(python.reason, InstallReason::Dependency);
+        let paru = reference.packages.keys().find(|p| p.name == "paru-bin").unwrap();
+        assert_eq!(paru.manager, PackageManager::AUR);
+        assert!(reference.packages.keys().any(|p| p.name == "vim"));
+    }
+
+    #[test]
+    fn structured_file_unknown_field() {
+        // When
+        let reference = read(Path::new("tests/data/structured_invalid"), &Facts::default());
+
+        // Then
+        assert!(matches!(reference, Err(ConfigReaderError::ParseError(message))
+            if message.starts_with("tests/data/structured_invalid/invalid.toml") && message.contains("unknown field")));
+    }
+
+    #[test]
+    fn held_packages() {
+        // When
+        let reference = read(Path::new("tests/data/hold"), &Facts::default()).unwrap();
+
+        // Then
+        let linux = PackageOrGroup::new("linux-lts".to_string(), PackageManager::PACMAN);
+        let paru = PackageOrGroup::new("paru-bin".to_string(), PackageManager::AUR);
+        assert_eq!(reference.held(), HashSet::from([linux.clone(), paru]));
+        assert_eq!(reference.declared().len(), 3);
+        assert_eq!(reference.files, BTreeSet::from([PathBuf::from("tests/data/hold/kernel")]));
+        assert_eq!(reference.hold_origins(&linux), &[Origin::new(PathBuf::from("tests/data/hold/kernel"), 2)]);
+        assert_eq!(reference.declared().iter().find(|p| p.name == "linux-lts").unwrap().version, Some("=6.6.*".to_string()));
+    }
+
+    #[test]
+    fn missing_directory() {
+        // When
+        let reference = read(Path::new("tests/data/nothing"), &Facts::default());
+
+        // Then
+        assert!(matches!(reference, Err(ConfigReaderError::MissingDirectory(path)) if path == Path::new("tests/data/nothing")));
+    }
+
+    #[test]
+    fn version_constraints() {
+        // Given
+        let origin = Origin::new(PathBuf::from("target/kernel"), 3);
+
+        // When
+        let linux = parse_line("pacman/core/linux-lts=6.6.*", &origin).unwrap();
+        let invalid = parse_line("linux-lts=", &origin);
+
+        // Then
+        assert_eq!(linux.name, "linux-lts");
+        assert_eq!(linux.repository, Some("core".to_string()));
+        assert_eq!(linux.version, Some("=6.6.*".to_string()));
+        assert!(matches!(invalid, Err(ConfigReaderError::ParseError(message))
+            if message == "target/kernel:3: version constraint without version: ="));
+    }
+
+    #[test]
+    fn version_constraints_from_files() {
+        // When
+        let reference = read(Path::new("tests/data/versions"), &Facts::default()).unwrap().declared();
+
+        // Then
+        let versions: HashSet<(String, Option<String>)> = reference.into_iter().map(|p| (p.name, p.version)).collect();
+        assert_eq!(versions, HashSet::from([
+            ("vim".to_string(), Some(">=9.0".to_string())),
+            ("linux-lts".to_string(), Some("=6.6.*".to_string())),
+        ]));
     }
 }
@@ -0,0 +1,108 @@
+/*
+ *  configuration/selection.rs
+ *
+ *  Copyright (c) 2024-2024 Cédric ROYER <cedric dot royer at zaclys dot net>
+ *
+ *  This program is free software; you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation; either version 2 of the License, or
+ *  (at your option) any later version.
+ *
+ *  This program is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::configuration::reader::{self, ConfigReaderError};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// File listing the active profiles, one per line, when none is given on the command line.
+pub const ACTIVE_PROFILES_FILE: &str = "active-profiles";
+
+/// Configuration fragments applying to this machine: the common `target` directory,
+/// the `hosts/<hostname>` directory and the `profiles/<name>` directory of each active profile.
+#[derive(PartialEq, Eq, Debug, Default)]
+pub struct Selection {
+    pub hostname: Option<String>,
+    pub profiles: Vec<String>,
+}
+
+impl Selection {
+    /// Selection for this machine: the hostname defaults to the kernel one and the profiles
+    /// to the ones listed in the active profiles file.
+    pub fn new(config_dir: &Path, hostname: Option<String>, profiles: Vec<String>) -> Result<Self, ConfigReaderError> {
+        let hostname = hostname.or_else(system_hostname);
+        let profiles = if profiles.is_empty() {
+            reader::read_list(&config_dir.join(ACTIVE_PROFILES_FILE))?
+        } else {
+            profiles
+        };
+        Ok(Selection{hostname, profiles})
+    }
+
+    /// Directories to read, a missing host directory is ignored but a missing profile is an error.
+    pub fn directories(&self, config_dir: &Path) -> Result<Vec<PathBuf>, ConfigReaderError> {
+        let mut directories = vec![config_dir.join("target")];
+        if let Some(hostname) = &self.hostname {
+            let host_dir = config_dir.join("hosts").join(hostname);
+            if host_dir.is_dir() {
+                directories.push(host_dir);
+            }
+        }
+        for profile in self.profiles.iter() {
+            let profile_dir = config_dir.join("profiles").join(profile);
+            if !profile_dir.is_dir() {
+                return Err(ConfigReaderError::UnknownProfile(profile.clone(), profile_dir));
+            }
+            directories.push(profile_dir);
+        }
+        Ok(directories)
+    }
+}
+
+fn system_hostname() -> Option<String> {
+    ["/proc/sys/kernel/hostname", "/etc/hostname"]
+        .iter()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .map(|content| content.trim().to_string())
+        .find(|hostname| !hostname.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn directories_for_host_and_profiles() {
+        // Given
+        let config_dir = Path::new("tests/data/config");
+
+        // When
+        let selection = Selection::new(config_dir, Some("laptop".to_string()), Vec::new()).unwrap();
+
+        // Then
+        assert_eq!(selection.profiles, vec!["dev".to_string()]);
+        assert_eq!(selection.directories(config_dir).unwrap(), vec![
+            PathBuf::from("tests/data/config/target"),
+            PathBuf::from("tests/data/config/hosts/laptop"),
+            PathBuf::from("tests/data/config/profiles/dev"),
+        ]);
+    }
+
+    #[test]
+    fn profiles_from_command_line() {
+        // Given
+        let config_dir = Path::new("tests/data/config");
+
+        // When
+        let selection = Selection::new(config_dir, Some("server".to_string()), vec!["gaming".to_string()]).unwrap();
+
+        // Then
+        assert!(matches!(selection.directories(config_dir), Err(ConfigReaderError::UnknownProfile(name, _)) if name == "gaming"));
+    }
+}
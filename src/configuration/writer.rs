@@ -0,0 +1,155 @@
+/*
+ *  configuration/writer.rs
+ *
+ *  Copyright (c) 2024-2024 Cédric ROYER <cedric dot royer at zaclys dot net>
+ *
+ *  This program is free software; you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation; either version 2 of the License, or
+ *  (at your option) any later version.
+ *
+ *  This program is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::engine::compute_actions::Package;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+
+/// File used when the export is not split by repository.
+pub const DEFAULT_FILE: &str = "exported";
+/// File used for packages that are not found in any repository.
+pub const FOREIGN_FILE: &str = "foreign";
+
+/// Build the content of target files from the installed packages.
+///
+/// Groups whose members are all installed are written as the group name.
+/// Packages that are not found in any repository are written with the
+/// `local/` prefix. Returns the lines of each file, indexed by file name.
+pub fn export(
+    installed: &HashSet<Package>,
+    groups: &HashMap<String, HashSet<String>>,
+    repositories: &HashMap<String, String>,
+    split_by_repository: bool,
+) -> BTreeMap<String, BTreeSet<String>> {
+    let installed_names: HashSet<&String> = installed.iter().map(|p| &p.name).collect();
+    let complete_groups: BTreeMap<&String, &HashSet<String>> = groups
+        .iter()
+        .filter(|(_, members)| !members.is_empty() && members.iter().all(|m| installed_names.contains(m)))
+        .collect();
+
+    let mut files: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    let file_for = |name: &String| {
+        if !split_by_repository {
+            DEFAULT_FILE.to_string()
+        } else {
+            repositories.get(name).cloned().unwrap_or(FOREIGN_FILE.to_string())
+        }
+    };
+
+    for (group, members) in complete_groups.iter() {
+        // a group lives in the repository of its members
+        let file = members.iter().min().map(file_for).unwrap_or(DEFAULT_FILE.to_string());
+        files.entry(file).or_default().insert(group.to_string());
+    }
+    for name in installed_names {
+        if complete_groups.values().any(|members| members.contains(name)) {
+            continue;
+        }
+        let line = if repositories.contains_key(name) {
+            name.clone()
+        } else {
+            format!("local/{}", name)
+        };
+        files.entry(file_for(name)).or_default().insert(line);
+    }
+    files
+}
+
+/// Write the exported files in `dir`, existing files are never overwritten.
+pub fn write(dir: &Path, files: &BTreeMap<String, BTreeSet<String>>) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    for (name, lines) in files {
+        let mut file = File::create_new(dir.join(name))?;
+        writeln!(file, "# exported by pacsync")?;
+        for line in lines {
+            writeln!(file, "{}", line)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::compute_actions::PackageManager;
+
+    type Given = (HashSet<Package>, HashMap<String, HashSet<String>>, HashMap<String, String>);
+
+    fn given() -> Given {
+        let mut installed = HashSet::new();
+        installed.insert(Package::new("baobab".to_string(), vec!["gnome".to_string()], PackageManager::PACMAN));
+        installed.insert(Package::new("gdm".to_string(), vec!["gnome".to_string()], PackageManager::PACMAN));
+        installed.insert(Package::new("gcc".to_string(), vec!["base-devel".to_string()], PackageManager::PACMAN));
+        installed.insert(Package::new("linux".to_string(), Vec::new(), PackageManager::PACMAN));
+        installed.insert(Package::new("steam".to_string(), Vec::new(), PackageManager::PACMAN));
+        installed.insert(Package::new("pacsync".to_string(), Vec::new(), PackageManager::PACMAN));
+
+        let mut groups = HashMap::new();
+        groups.insert("gnome".to_string(), HashSet::from(["baobab".to_string(), "gdm".to_string()]));
+        groups.insert("base-devel".to_string(), HashSet::from(["gcc".to_string(), "make".to_string()]));
+
+        let mut repositories = HashMap::new();
+        repositories.insert("baobab".to_string(), "extra".to_string());
+        repositories.insert("gdm".to_string(), "extra".to_string());
+        repositories.insert("gcc".to_string(), "core".to_string());
+        repositories.insert("make".to_string(), "core".to_string());
+        repositories.insert("linux".to_string(), "core".to_string());
+        repositories.insert("steam".to_string(), "multilib".to_string());
+        (installed, groups, repositories)
+    }
+
+    #[test]
+    fn export_single_file() {
+        // Given
+        let (installed, groups, repositories) = given();
+        let mut expected = BTreeMap::new();
+        expected.insert(DEFAULT_FILE.to_string(), BTreeSet::from([
+            "gnome".to_string(),
+            "gcc".to_string(),
+            "linux".to_string(),
+            "steam".to_string(),
+            "local/pacsync".to_string(),
+        ]));
+
+        // When
+        let files = export(&installed, &groups, &repositories, false);
+
+        // Then
+        assert_eq!(files, expected)
+    }
+
+    #[test]
+    fn export_split_by_repository() {
+        // Given
+        let (installed, groups, repositories) = given();
+        let mut expected = BTreeMap::new();
+        expected.insert("core".to_string(), BTreeSet::from(["gcc".to_string(), "linux".to_string()]));
+        expected.insert("extra".to_string(), BTreeSet::from(["gnome".to_string()]));
+        expected.insert("multilib".to_string(), BTreeSet::from(["steam".to_string()]));
+        expected.insert(FOREIGN_FILE.to_string(), BTreeSet::from(["local/pacsync".to_string()]));
+
+        // When
+        let files = export(&installed, &groups, &repositories, true);
+
+        // Then
+        assert_eq!(files, expected)
+    }
+}
@@ -18,3 +18,7 @@
  */
 
 pub mod compute_actions;
+pub mod effects;
+pub mod plan;
+pub mod reference;
+pub mod version;
@@ -17,36 +17,113 @@
  *  along with this program.  If not, see <http://www.gnu.org/licenses/>.
  */
 
-use std::{collections::HashSet, fmt::{Debug, Display}, hash::Hash};
+use crate::engine::version::Constraint;
+use serde::{Deserialize, Serialize};
+use std::{collections::{HashMap, HashSet}, fmt::{Debug, Display}, hash::Hash};
 
-#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum PackageManager {
     PACMAN,
     LOCAL,
+    AUR,
+    FLATPAK,
 }
 
-#[derive(PartialEq, Eq, Hash, Debug, Clone)]
+/// Why a package is installed, as recorded by pacman.
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InstallReason {
+    #[default]
+    Explicit,
+    /// installed as a dependency, a declared dependency is satisfied once installed and never removed
+    Dependency,
+}
+
+#[derive(Eq, Debug, Clone)]
 pub struct PackageOrGroup {
     pub name: String,
     pub manager: PackageManager,
+    /// repository (or flatpak remote) to install from
+    pub repository: Option<String>,
+    pub reason: InstallReason,
+    /// required version, like `>=1.2`
+    pub version: Option<String>,
+    /// declared group, when the package is added as one of its members
+    pub group: Option<String>,
+}
+
+impl Hash for PackageOrGroup {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+        self.manager.hash(state);
+    }
+}
+
+impl PartialEq for PackageOrGroup {
+    fn eq(&self, other: &Self) -> bool {
+        self.name.eq(&other.name) && self.manager.eq(&other.manager)
+    }
 }
 
 impl PackageOrGroup {
     pub fn new(name: String, manager: PackageManager) -> Self {
-        PackageOrGroup{name, manager}
+        PackageOrGroup{name, manager, repository: None, reason: InstallReason::Explicit, version: None, group: None}
+    }
+
+    pub fn with_repository(mut self, repository: String) -> Self {
+        self.repository = Some(repository);
+        self
+    }
+
+    pub fn with_reason(mut self, reason: InstallReason) -> Self {
+        self.reason = reason;
+        self
+    }
+
+    pub fn with_version(mut self, version: String) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    pub fn with_group(mut self, group: String) -> Self {
+        self.group = Some(group);
+        self
     }
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Default)]
 pub struct Actions {
     pub to_add: HashSet<PackageOrGroup>,
     pub to_delete: HashSet<PackageOrGroup>,
+    /// installed packages whose install reason changes to the one they carry: declared packages
+    /// installed as dependencies, and undeclared ones still required by other packages
+    pub to_mark: HashSet<PackageOrGroup>,
+    /// excluded packages, never installed even as members of a group to add
+    pub excluded: HashSet<PackageOrGroup>,
+    /// packages installed from another repository than the declared one, with this repository,
+    /// they are installed again from the declared one
+    pub drifted: HashMap<PackageOrGroup, String>,
+    /// installed packages whose version does not satisfy the declared constraint, with this version
+    pub unsatisfied: HashMap<PackageOrGroup, String>,
+    /// packages held back from upgrades, the whole hold list is written when it changes
+    pub held: HashSet<PackageOrGroup>,
+    pub to_hold: HashSet<PackageOrGroup>,
+    pub to_release: HashSet<PackageOrGroup>,
 }
 
 #[derive(Eq, Debug)]
 pub struct Package {
-    name: String,
-    group: Option<String>,
+    pub name: String,
+    pub groups: Vec<String>,
+    pub manager: PackageManager,
+    pub reason: InstallReason,
+    /// sync repositories providing the installed version, empty if unknown
+    pub repositories: Vec<String>,
+    /// installed version, when known
+    pub version: Option<String>,
+    /// required by another installed package, it cannot be removed alone
+    pub required: bool,
 }
 
 impl Hash for Package {
@@ -63,33 +140,128 @@ impl PartialEq for Package {
 
 
 impl Package {
-    pub fn new(name: String, group: Option<String>) -> Package {
-        Package{name, group}
+    pub fn new(name: String, groups: Vec<String>, manager: PackageManager) -> Package {
+        Package{name, groups, manager, reason: InstallReason::Explicit, repositories: Vec::new(), version: None, required: false}
+    }
+
+    pub fn with_reason(mut self, reason: InstallReason) -> Self {
+        self.reason = reason;
+        self
+    }
+
+    pub fn with_repositories(mut self, repositories: Vec<String>) -> Self {
+        self.repositories = repositories;
+        self
+    }
+
+    pub fn with_version(mut self, version: String) -> Self {
+        self.version = Some(version);
+        self
+    }
+
+    pub fn with_required(mut self, required: bool) -> Self {
+        self.required = required;
+        self
     }
 }
 
-pub fn compute_actions(reference: HashSet<PackageOrGroup>, current: HashSet<Package>) -> Actions {
+/// Only explicitly installed packages are deleted, the ones installed as dependencies only satisfy
+/// the declarations.
+/// Declared groups found in `groups`, the members of every known group, are expanded: their missing
+/// members are added. Other declared groups are satisfied by any installed member.
+/// Excluded packages, or members of excluded groups, are deleted even when their group is declared.
+/// Undeclared packages required by other packages are marked as dependencies instead of being deleted,
+/// declared packages are marked with their declared install reason.
+/// Version constraints are only checked for installed packages whose version is known.
+pub fn compute_actions(
+    reference: HashSet<PackageOrGroup>,
+    current: HashSet<Package>,
+    excluded: HashSet<PackageOrGroup>,
+    groups: &HashMap<String, HashSet<String>>,
+) -> Actions {
+    let explicit: Vec<&Package> = current.iter().filter(|p| p.reason == InstallReason::Explicit).collect();
     let current_groups: std::collections::HashSet<&String> =
-        HashSet::from_iter(current.iter().filter_map(|p| p.group.as_ref()));
+        HashSet::from_iter(explicit.iter().flat_map(|p| p.groups.iter()));
     let current_packages: std::collections::HashSet<&String> =
         HashSet::from_iter(current.iter().map(|p| &p.name));
     let reference_packages: std::collections::HashSet<&String> =
         HashSet::from_iter(reference.iter().map(|p| &p.name));
+    let excluded_packages: std::collections::HashSet<&String> =
+        HashSet::from_iter(excluded.iter().map(|p| &p.name));
+    // members of the declared groups, they are declared by their group
+    let members: Vec<PackageOrGroup> = reference
+        .iter()
+        .filter_map(|group| Some((group, groups.get(&group.name)?)))
+        .flat_map(|(group, members)| {
+            members.iter().map(|member| {
+                PackageOrGroup::new(member.clone(), group.manager.clone()).with_reason(group.reason).with_group(group.name.clone())
+            })
+        })
+        .filter(|member| !reference.contains(member))
+        .collect();
+    let declared_members: std::collections::HashSet<&String> =
+        HashSet::from_iter(members.iter().map(|p| &p.name));
     let to_add = HashSet::from_iter(
         reference
         .iter()
+        .filter(|&p_or_g| !groups.contains_key(&p_or_g.name))
         .filter(|&p_or_g| {
             !(current_packages.contains(&p_or_g.name) || current_groups.contains(&p_or_g.name))
         })
-        .map(|p_or_g| p_or_g.clone()),
+        .chain(members.iter().filter(|&member| !current_packages.contains(&member.name) && !excluded_packages.contains(&member.name)))
+        .cloned(),
     );
-    let to_delete = HashSet::from_iter(
-        current
-        .iter()
-        .filter(|&p| !(reference_packages.contains(&p.name) || p.group.as_ref().is_some_and(|g| reference_packages.contains(g))))
-        .map(|p| PackageOrGroup::new(p.name.clone(), PackageManager::PACMAN)),
+    let (to_delete, required): (Vec<&Package>, Vec<&Package>) = explicit
+        .into_iter()
+        .filter(|&p| {
+            let excluded = excluded_packages.contains(&p.name) || p.groups.iter().any(|g| excluded_packages.contains(g));
+            let declared = reference_packages.contains(&p.name)
+                || declared_members.contains(&p.name)
+                || p.groups.iter().any(|g| reference_packages.contains(g));
+            excluded || !declared
+        })
+        // removing a required package would break its dependents, even when it is excluded
+        .partition(|&p| !p.required);
+    let to_delete = HashSet::from_iter(to_delete.into_iter().map(|p| PackageOrGroup::new(p.name.clone(), p.manager.clone())));
+    let to_mark = HashSet::from_iter(
+        required
+        .into_iter()
+        .map(|p| PackageOrGroup::new(p.name.clone(), p.manager.clone()).with_reason(InstallReason::Dependency))
+        .chain(reference.iter().filter_map(|p_or_g| {
+            let installed = current.get(&Package::new(p_or_g.name.clone(), Vec::new(), p_or_g.manager.clone()))?;
+            (installed.reason != p_or_g.reason).then(|| p_or_g.clone())
+        })),
     );
-    Actions {to_add, to_delete}
+    // the repository of a package is known when the installed version is in a sync repository
+    let drifted = HashMap::from_iter(reference.iter().filter_map(|p_or_g| {
+        let declared = p_or_g.repository.as_ref()?;
+        let installed = current.get(&Package::new(p_or_g.name.clone(), Vec::new(), p_or_g.manager.clone()))?;
+        if installed.repositories.is_empty() || installed.repositories.contains(declared) {
+            return None;
+        }
+        Some((p_or_g.clone(), installed.repositories.join(", ")))
+    }));
+    let unsatisfied = HashMap::from_iter(reference.iter().filter_map(|p_or_g| {
+        let constraint = Constraint::parse(p_or_g.version.as_ref()?).ok()?;
+        let installed = current.get(&Package::new(p_or_g.name.clone(), Vec::new(), p_or_g.manager.clone()))?.version.as_ref()?;
+        if constraint.is_satisfied_by(installed) {
+            return None;
+        }
+        Some((p_or_g.clone(), installed.clone()))
+    }));
+    Actions {to_add, to_delete, to_mark, excluded, drifted, unsatisfied, ..Default::default()}
+}
+
+/// Compare the declared holds with the packages currently held back from upgrades.
+pub fn compute_holds(held: HashSet<PackageOrGroup>, current: HashSet<Package>) -> Actions {
+    let held_names: HashSet<&String> = held.iter().map(|p| &p.name).collect();
+    let to_hold = held.iter().filter(|p_or_g| !current.contains(&Package::new(p_or_g.name.clone(), Vec::new(), p_or_g.manager.clone()))).cloned().collect();
+    let to_release = current
+        .iter()
+        .filter(|p| !held_names.contains(&p.name))
+        .map(|p| PackageOrGroup::new(p.name.clone(), p.manager.clone()))
+        .collect();
+    Actions {held, to_hold, to_release, ..Default::default()}
 }
 
 impl Display for Actions {
@@ -99,15 +271,49 @@ impl Display for Actions {
             return Ok(());
         }
         if !self.to_add.is_empty() {
-            write!(f, "To add:\n")?;
+            writeln!(f, "To add:")?;
             for package_or_group in self.to_add.iter() {
-                write!(f, "\t- {} ({})\n", package_or_group.name, package_or_group.manager)?;
+                writeln!(f, "\t- {} ({})", package_or_group.name, package_or_group.manager)?;
             }
         }
         if !self.to_delete.is_empty() {
-            write!(f, "To delete:\n")?;
+            writeln!(f, "To delete:")?;
             for package_or_group in self.to_delete.iter() {
-                write!(f, "\t- {} ({})\n", package_or_group.name, package_or_group.manager)?;
+                writeln!(f, "\t- {} ({})", package_or_group.name, package_or_group.manager)?;
+            }
+        }
+        for (reason, title) in [(InstallReason::Dependency, "To mark as dependencies:"), (InstallReason::Explicit, "To mark as explicitly installed:")] {
+            let marked: Vec<&PackageOrGroup> = self.to_mark.iter().filter(|p| p.reason == reason).collect();
+            if !marked.is_empty() {
+                writeln!(f, "{}", title)?;
+                for package_or_group in marked {
+                    writeln!(f, "\t- {} ({})", package_or_group.name, package_or_group.manager)?;
+                }
+            }
+        }
+        if !self.drifted.is_empty() {
+            writeln!(f, "To install again from the declared repository:")?;
+            for (package_or_group, installed) in self.drifted.iter() {
+                let declared = package_or_group.repository.as_deref().unwrap_or_default();
+                writeln!(f, "\t- {} ({}): installed from {}, declared in {}", package_or_group.name, package_or_group.manager, installed, declared)?;
+            }
+        }
+        if !self.unsatisfied.is_empty() {
+            writeln!(f, "Version constraints not satisfied:")?;
+            for (package_or_group, installed) in self.unsatisfied.iter() {
+                writeln!(f, "\t- {}", describe_unsatisfied(package_or_group, installed))?;
+            }
+        }
+        if !self.to_hold.is_empty() {
+            writeln!(f, "To hold back from upgrades:")?;
+            for package_or_group in self.to_hold.iter() {
+                writeln!(f, "\t- {} ({})", package_or_group.name, package_or_group.manager)?;
+            }
+        }
+        if !self.to_release.is_empty() {
+            writeln!(f, "To release:")?;
+            for package_or_group in self.to_release.iter() {
+                writeln!(f, "\t- {} ({})", package_or_group.name, package_or_group.manager)?;
             }
         }
         Ok(())
@@ -116,15 +322,45 @@ impl Display for Actions {
 
 impl Actions {
     pub fn is_empty(&self) -> bool {
-        self.to_add.is_empty() && self.to_delete.is_empty()
+        self.to_add.is_empty() && self.to_delete.is_empty() && self.to_mark.is_empty() && self.drifted.is_empty() && self.unsatisfied.is_empty()
+            && self.to_hold.is_empty() && self.to_release.is_empty()
+    }
+
+    /// Take the unsatisfied version constraints out of the actions, when nothing installs the
+    /// required versions they are only reported.
+    pub fn take_unsatisfied(&mut self) -> Vec<String> {
+        let mut unsatisfied: Vec<String> = self.unsatisfied.drain().map(|(p_or_g, installed)| describe_unsatisfied(&p_or_g, &installed)).collect();
+        unsatisfied.sort();
+        unsatisfied
     }
+
+    pub fn extend(&mut self, other: Actions) {
+        self.to_add.extend(other.to_add);
+        self.to_delete.extend(other.to_delete);
+        self.to_mark.extend(other.to_mark);
+        self.excluded.extend(other.excluded);
+        self.drifted.extend(other.drifted);
+        self.unsatisfied.extend(other.unsatisfied);
+        self.held.extend(other.held);
+        self.to_hold.extend(other.to_hold);
+        self.to_release.extend(other.to_release);
+    }
+}
+
+/// `linux-lts (pacman): installed 6.9.7-1, required =6.6.* (downgrade)`
+fn describe_unsatisfied(p_or_g: &PackageOrGroup, installed: &str) -> String {
+    let required = p_or_g.version.as_deref().unwrap_or_default();
+    let downgrade = Constraint::parse(required).is_ok_and(|c| c.needs_downgrade(installed));
+    format!("{} ({}): installed {}, required {}{}", p_or_g.name, p_or_g.manager, installed, required, if downgrade { " (downgrade)" } else { "" })
 }
 
 impl Display for PackageManager {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             PackageManager::PACMAN => write!(f, "pacman"),
-            PackageManager::LOCAL => write!(f, "local")
+            PackageManager::LOCAL => write!(f, "local"),
+            PackageManager::AUR => write!(f, "aur"),
+            PackageManager::FLATPAK => write!(f, "flatpak"),
         }
     }
 }
@@ -146,12 +382,12 @@ mod tests {
         reference.insert(PackageOrGroup::new("to_add2".to_string(), PackageManager::LOCAL));
 
         let mut current = HashSet::new();
-        current.insert(Package::new("to_keep1".to_string(), Some("a group".to_string())));
-        current.insert(Package::new("a package".to_string(), Some("to_keep2".to_string())));
-        current.insert(Package::new("another package".to_string(),Some("to_keep2".to_string())));
-        current.insert(Package::new("to_keep3".to_string(), None));
-        current.insert(Package::new("to_rm1".to_string(), Some("a group".to_string())));
-        current.insert(Package::new("to_rm2".to_string(), None));
+        current.insert(Package::new("to_keep1".to_string(), vec!["a group".to_string()], PackageManager::PACMAN));
+        current.insert(Package::new("a package".to_string(), vec!["to_keep2".to_string()], PackageManager::PACMAN));
+        current.insert(Package::new("another package".to_string(),vec!["to_keep2".to_string()], PackageManager::PACMAN));
+        current.insert(Package::new("to_keep3".to_string(), Vec::new(), PackageManager::PACMAN));
+        current.insert(Package::new("to_rm1".to_string(), vec!["a group".to_string()], PackageManager::PACMAN));
+        current.insert(Package::new("to_rm2".to_string(), Vec::new(), PackageManager::PACMAN));
 
         let mut expected_to_add = HashSet::new();
         expected_to_add.insert(PackageOrGroup::new("to_add1".to_string(), PackageManager::PACMAN));
@@ -162,23 +398,213 @@ mod tests {
         expected_to_rm.insert(PackageOrGroup::new("to_rm2".to_string(), PackageManager::PACMAN));
 
         // When
-        let actions = compute_actions(reference, current);
+        let actions = compute_actions(reference, current, HashSet::new(), &HashMap::new());
 
         // Then
         assert_eq!(
             actions,
             Actions {
                 to_add: expected_to_add,
-                to_delete: expected_to_rm
+                to_delete: expected_to_rm,
+                ..Default::default()
             }
         );
     }
 
+    #[test]
+    fn dependencies_satisfy_declarations() {
+        // Given
+        let reference = HashSet::from([PackageOrGroup::new("python".to_string(), PackageManager::PACMAN).with_reason(InstallReason::Dependency)]);
+        let current = HashSet::from([
+            Package::new("python".to_string(), Vec::new(), PackageManager::PACMAN).with_reason(InstallReason::Dependency),
+            Package::new("glibc".to_string(), Vec::new(), PackageManager::PACMAN).with_reason(InstallReason::Dependency),
+        ]);
+
+        // When
+        let actions = compute_actions(reference, current, HashSet::new(), &HashMap::new());
+
+        // Then
+        assert!(actions.is_empty());
+    }
+
+    #[test]
+    fn install_reasons_reconciled() {
+        // Given
+        let reference = HashSet::from([
+            PackageOrGroup::new("python".to_string(), PackageManager::PACMAN),
+            PackageOrGroup::new("vim".to_string(), PackageManager::PACMAN),
+        ]);
+        let current = HashSet::from([
+            Package::new("python".to_string(), Vec::new(), PackageManager::PACMAN).with_reason(InstallReason::Dependency),
+            Package::new("vim".to_string(), Vec::new(), PackageManager::PACMAN),
+            Package::new("glibc".to_string(), Vec::new(), PackageManager::PACMAN).with_required(true),
+            Package::new("nano".to_string(), Vec::new(), PackageManager::PACMAN),
+        ]);
+
+        // When
+        let actions = compute_actions(reference, current, HashSet::new(), &HashMap::new());
+
+        // Then
+        assert_eq!(actions.to_delete, HashSet::from([PackageOrGroup::new("nano".to_string(), PackageManager::PACMAN)]));
+        let marked: HashSet<(String, InstallReason)> = actions.to_mark.iter().map(|p| (p.name.clone(), p.reason)).collect();
+        assert_eq!(marked, HashSet::from([
+            ("glibc".to_string(), InstallReason::Dependency),
+            ("python".to_string(), InstallReason::Explicit),
+        ]));
+    }
+
+    #[test]
+    fn required_excluded_package_marked() {
+        // Given
+        let reference = HashSet::from([PackageOrGroup::new("vim".to_string(), PackageManager::PACMAN)]);
+        let current = HashSet::from([
+            Package::new("vim".to_string(), Vec::new(), PackageManager::PACMAN),
+            Package::new("python".to_string(), Vec::new(), PackageManager::PACMAN).with_required(true),
+        ]);
+        let excluded = HashSet::from([PackageOrGroup::new("python".to_string(), PackageManager::PACMAN)]);
+
+        // When
+        let actions = compute_actions(reference, current, excluded, &HashMap::new());
+
+        // Then
+        assert!(actions.to_delete.is_empty());
+        let marked: HashSet<(String, InstallReason)> = actions.to_mark.iter().map(|p| (p.name.clone(), p.reason)).collect();
+        assert_eq!(marked, HashSet::from([("python".to_string(), InstallReason::Dependency)]));
+    }
+
+    #[test]
+    fn repository_drift() {
+        // Given
+        let firefox = PackageOrGroup::new("firefox".to_string(), PackageManager::PACMAN).with_repository("private".to_string());
+        let vim = PackageOrGroup::new("vim".to_string(), PackageManager::PACMAN).with_repository("extra".to_string());
+        let reference = HashSet::from([firefox.clone(), vim]);
+        let current = HashSet::from([
+            Package::new("firefox".to_string(), Vec::new(), PackageManager::PACMAN).with_repositories(vec!["extra".to_string()]),
+            Package::new("vim".to_string(), Vec::new(), PackageManager::PACMAN).with_repositories(vec!["extra".to_string()]),
+        ]);
+
+        // When
+        let actions = compute_actions(reference, current, HashSet::new(), &HashMap::new());
+
+        // Then
+        assert_eq!(actions.drifted, HashMap::from([(firefox, "extra".to_string())]));
+        assert!(actions.to_add.is_empty());
+    }
+
+    #[test]
+    fn version_constraints() {
+        // Given
+        let linux = PackageOrGroup::new("linux-lts".to_string(), PackageManager::PACMAN).with_version("=6.6.*".to_string());
+        let vim = PackageOrGroup::new("vim".to_string(), PackageManager::PACMAN).with_version(">=9.0".to_string());
+        let firefox = PackageOrGroup::new("firefox".to_string(), PackageManager::PACMAN).with_version(">=120".to_string());
+        let reference = HashSet::from([linux.clone(), vim, firefox.clone()]);
+        let current = HashSet::from([
+            Package::new("linux-lts".to_string(), Vec::new(), PackageManager::PACMAN).with_version("6.9.7-1".to_string()),
+            Package::new("vim".to_string(), Vec::new(), PackageManager::PACMAN).with_version("9.1.0-1".to_string()),
+        ]);
+
+        // When
+        let actions = compute_actions(reference, current, HashSet::new(), &HashMap::new());
+
+        // Then
+        assert_eq!(actions.unsatisfied, HashMap::from([(linux, "6.9.7-1".to_string())]));
+        assert_eq!(actions.to_add, HashSet::from([firefox]));
+        assert!(actions.to_string().contains("\t- linux-lts (pacman): installed 6.9.7-1, required =6.6.* (downgrade)\n"));
+    }
+
+    #[test]
+    fn reported_version_constraints() {
+        // Given
+        let linux = PackageOrGroup::new("linux-lts".to_string(), PackageManager::PACMAN).with_version("=6.6.*".to_string());
+        let mut actions = Actions {unsatisfied: HashMap::from([(linux, "6.9.7-1".to_string())]), ..Default::default()};
+
+        // When
+        let unsatisfied = actions.take_unsatisfied();
+
+        // Then
+        assert_eq!(unsatisfied, vec!["linux-lts (pacman): installed 6.9.7-1, required =6.6.* (downgrade)".to_string()]);
+        assert!(actions.is_empty());
+    }
+
+    #[test]
+    fn holds() {
+        // Given
+        let linux = PackageOrGroup::new("linux-lts".to_string(), PackageManager::PACMAN);
+        let mesa = PackageOrGroup::new("mesa".to_string(), PackageManager::PACMAN);
+        let current = HashSet::from([
+            Package::new("linux-lts".to_string(), Vec::new(), PackageManager::PACMAN),
+            Package::new("nvidia-dkms".to_string(), Vec::new(), PackageManager::PACMAN),
+        ]);
+
+        // When
+        let actions = compute_holds(HashSet::from([linux.clone(), mesa.clone()]), current);
+
+        // Then
+        assert_eq!(actions, Actions {
+            held: HashSet::from([linux, mesa.clone()]),
+            to_hold: HashSet::from([mesa]),
+            to_release: HashSet::from([PackageOrGroup::new("nvidia-dkms".to_string(), PackageManager::PACMAN)]),
+            ..Default::default()
+        });
+    }
+
+    #[test]
+    fn excluded_group_member() {
+        // Given
+        let reference = HashSet::from([PackageOrGroup::new("gnome".to_string(), PackageManager::PACMAN)]);
+        let current = HashSet::from([
+            Package::new("baobab".to_string(), vec!["gnome".to_string()], PackageManager::PACMAN),
+            Package::new("epiphany".to_string(), vec!["gnome".to_string()], PackageManager::PACMAN),
+        ]);
+        let excluded = HashSet::from([PackageOrGroup::new("epiphany".to_string(), PackageManager::PACMAN)]);
+
+        // When
+        let actions = compute_actions(reference, current, excluded.clone(), &HashMap::new());
+
+        // Then
+        assert_eq!(
+            actions,
+            Actions {
+                to_add: HashSet::new(),
+                to_delete: HashSet::from([PackageOrGroup::new("epiphany".to_string(), PackageManager::PACMAN)]),
+                excluded,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn groups_expanded_from_members() {
+        // Given
+        let gnome = PackageOrGroup::new("gnome".to_string(), PackageManager::PACMAN);
+        let reference = HashSet::from([gnome.clone()]);
+        let current = HashSet::from([
+            Package::new("baobab".to_string(), vec!["gnome".to_string()], PackageManager::PACMAN),
+            Package::new("gdm".to_string(), Vec::new(), PackageManager::PACMAN),
+        ]);
+        let excluded = HashSet::from([PackageOrGroup::new("epiphany".to_string(), PackageManager::PACMAN)]);
+        let groups = HashMap::from([(
+            "gnome".to_string(),
+            HashSet::from(["baobab".to_string(), "epiphany".to_string(), "gdm".to_string(), "nautilus".to_string(), "totem".to_string()]),
+        )]);
+
+        // When
+        let actions = compute_actions(reference, current, excluded, &groups);
+
+        // Then
+        assert_eq!(actions.to_add, HashSet::from([
+            PackageOrGroup::new("nautilus".to_string(), PackageManager::PACMAN),
+            PackageOrGroup::new("totem".to_string(), PackageManager::PACMAN),
+        ]));
+        assert!(actions.to_add.iter().all(|p| p.group == Some("gnome".to_string())));
+        assert!(actions.to_delete.is_empty());
+    }
+
     #[test]
     fn package_hash() {
         // Given
-        let package_with_group = Package::new("baobab".to_string(), Some("gnome".to_string()));
-        let package_without_group =Package::new("baobab".to_string(), None);
+        let package_with_group = Package::new("baobab".to_string(), vec!["gnome".to_string()], PackageManager::PACMAN);
+        let package_without_group =Package::new("baobab".to_string(), Vec::new(), PackageManager::PACMAN);
         let mut hasher_with_group = DefaultHasher::new();
         let mut hasher_without_group = DefaultHasher::new();
 
@@ -0,0 +1,191 @@
+/*
+ *  engine/effects.rs
+ *
+ *  Copyright (c) 2024-2024 Cédric ROYER <cedric dot royer at zaclys dot net>
+ *
+ *  This program is free software; you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation; either version 2 of the License, or
+ *  (at your option) any later version.
+ *
+ *  This program is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use std::collections::BTreeSet;
+use std::fmt::Display;
+
+/// Transitive effects of the actions on the system.
+#[derive(PartialEq, Debug, Default)]
+pub struct Effects {
+    /// dependencies installed along with the added packages
+    pub pulled_in: BTreeSet<String>,
+    /// dependencies no longer required by any package once the deleted packages are removed
+    pub orphaned: BTreeSet<String>,
+    /// dependencies removed along with the deleted packages
+    pub removed_dependencies: BTreeSet<String>,
+    /// size to download, in bytes
+    pub download_size: u64,
+    /// size of the installed packages, in bytes
+    pub installed_size: u64,
+    /// size of the removed packages, in bytes
+    pub removed_size: u64,
+    /// problems reported by the package manager while simulating the actions
+    pub warnings: Vec<String>,
+}
+
+impl Effects {
+    pub fn is_empty(&self) -> bool {
+        *self == Effects::default()
+    }
+
+    pub fn extend(&mut self, other: Effects) {
+        self.pulled_in.extend(other.pulled_in);
+        self.orphaned.extend(other.orphaned);
+        self.removed_dependencies.extend(other.removed_dependencies);
+        self.download_size += other.download_size;
+        self.installed_size += other.installed_size;
+        self.removed_size += other.removed_size;
+        self.warnings.extend(other.warnings);
+    }
+}
+
+impl Display for Effects {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if !self.pulled_in.is_empty() {
+            writeln!(f, "Pulled in dependencies:")?;
+            for name in self.pulled_in.iter() {
+                writeln!(f, "\t- {}", name)?;
+            }
+        }
+        if !self.orphaned.is_empty() {
+            writeln!(f, "New orphans:")?;
+            for name in self.orphaned.iter() {
+                writeln!(f, "\t- {}", name)?;
+            }
+        }
+        if !self.removed_dependencies.is_empty() {
+            writeln!(f, "Removed dependencies:")?;
+            for name in self.removed_dependencies.iter() {
+                writeln!(f, "\t- {}", name)?;
+            }
+        }
+        if self.download_size > 0 {
+            writeln!(f, "Download size: {}", format_size(self.download_size))?;
+        }
+        if self.installed_size > 0 {
+            writeln!(f, "Installed size: {}", format_size(self.installed_size))?;
+        }
+        if self.removed_size > 0 {
+            writeln!(f, "Removed size: {}", format_size(self.removed_size))?;
+        }
+        for warning in self.warnings.iter() {
+            writeln!(f, "Warning: {}", warning)?;
+        }
+        Ok(())
+    }
+}
+
+/// Outcome of the transaction simulated by the package managers, nothing being changed.
+#[derive(PartialEq, Debug, Default)]
+pub struct Simulation {
+    /// packages that would be installed, with their version and repository when known
+    pub installed: Vec<String>,
+    /// packages that would be removed, with their version
+    pub removed: Vec<String>,
+    /// conflicts, replacements and errors reported by the package manager
+    pub notes: Vec<String>,
+}
+
+impl Simulation {
+    pub fn extend(&mut self, other: Simulation) {
+        self.installed.extend(other.installed);
+        self.removed.extend(other.removed);
+        self.notes.extend(other.notes);
+    }
+}
+
+impl Display for Simulation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.installed.is_empty() && self.removed.is_empty() && self.notes.is_empty() {
+            return writeln!(f, "Nothing would change");
+        }
+        if !self.installed.is_empty() {
+            writeln!(f, "Would install:")?;
+            for package in self.installed.iter() {
+                writeln!(f, "\t- {}", package)?;
+            }
+        }
+        if !self.removed.is_empty() {
+            writeln!(f, "Would remove:")?;
+            for package in self.removed.iter() {
+                writeln!(f, "\t- {}", package)?;
+            }
+        }
+        for note in self.notes.iter() {
+            writeln!(f, "Note: {}", note)?;
+        }
+        Ok(())
+    }
+}
+
+/// Human readable size, with the binary units used by pacman.
+pub fn format_size(bytes: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, units[unit])
+    } else {
+        format!("{:.2} {}", size, units[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_size_units() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.50 KiB");
+        assert_eq!(format_size(3 * 1024 * 1024 * 1024), "3.00 GiB");
+    }
+
+    #[test]
+    fn extend_sums_sizes() {
+        // Given
+        let mut effects = Effects {
+            pulled_in: BTreeSet::from(["a".to_string()]),
+            download_size: 10,
+            ..Default::default()
+        };
+        let other = Effects {
+            orphaned: BTreeSet::from(["b".to_string()]),
+            download_size: 5,
+            removed_size: 3,
+            ..Default::default()
+        };
+
+        // When
+        effects.extend(other);
+
+        // Then
+        assert_eq!(effects, Effects {
+            pulled_in: BTreeSet::from(["a".to_string()]),
+            orphaned: BTreeSet::from(["b".to_string()]),
+            download_size: 15,
+            removed_size: 3,
+            ..Default::default()
+        });
+    }
+}
@@ -0,0 +1,287 @@
+/*
+ *  engine/plan.rs
+ *
+ *  Copyright (c) 2024-2024 Cédric ROYER <cedric dot royer at zaclys dot net>
+ *
+ *  This program is free software; you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation; either version 2 of the License, or
+ *  (at your option) any later version.
+ *
+ *  This program is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::process::Command;
+use crate::engine::compute_actions::{Actions, InstallReason, PackageManager, PackageOrGroup};
+use crate::engine::reference::{self, Annotation, Reference};
+use crate::engine::version::Constraint;
+
+/// Machine-readable plan, sorted so that the plans of several hosts can be compared.
+#[derive(PartialEq, Debug, Default, Serialize)]
+pub struct Plan {
+    pub actions: Vec<PlannedAction>,
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Operation {
+    Install,
+    Remove,
+    /// change the install reason
+    Mark,
+    /// install again from the declared repository
+    Reinstall,
+    /// install a newer version satisfying the constraint
+    Upgrade,
+    /// install an older version satisfying the constraint
+    Downgrade,
+    /// hold back from upgrades
+    Hold,
+    /// no longer hold back from upgrades
+    Release,
+}
+
+impl Display for Operation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Operation::Install => write!(f, "install"),
+            Operation::Remove => write!(f, "remove"),
+            Operation::Mark => write!(f, "mark"),
+            Operation::Reinstall => write!(f, "reinstall"),
+            Operation::Upgrade => write!(f, "upgrade"),
+            Operation::Downgrade => write!(f, "downgrade"),
+            Operation::Hold => write!(f, "hold"),
+            Operation::Release => write!(f, "release"),
+        }
+    }
+}
+
+/// An action on a package, with the commands applying it.
+#[derive(PartialEq, Debug, Serialize)]
+pub struct PlannedAction {
+    pub name: String,
+    pub manager: PackageManager,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repository: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    pub operation: Operation,
+    /// why the action is needed, with the places declaring the package
+    pub reason: String,
+    /// comment and owner of the declaration
+    #[serde(flatten)]
+    pub annotation: Annotation,
+    /// commands concerning the package, as printed by `pacsync print`
+    pub commands: Vec<String>,
+}
+
+impl Plan {
+    /// Describe the actions, each one with the commands naming its package, alone or prefixed by
+    /// its repository. The hold list is replaced as a whole: the holds and releases all come with
+    /// the commands replacing it.
+    pub fn new(actions: &Actions, commands: &[Command], hold_commands: &[Command], reference: &Reference) -> Plan {
+        let planned = |p_or_g: &PackageOrGroup, operation: Operation, reason: String| PlannedAction {
+            name: p_or_g.name.clone(),
+            manager: p_or_g.manager.clone(),
+            repository: p_or_g.repository.clone(),
+            version: p_or_g.version.clone(),
+            operation,
+            reason,
+            annotation: reference.annotation(p_or_g).cloned().unwrap_or_default(),
+            commands: match operation {
+                Operation::Hold | Operation::Release => hold_commands.iter().map(|cmd| format!("{:?}", cmd)).collect(),
+                _ => commands
+                    .iter()
+                    .filter(|cmd| {
+                        cmd.get_args().any(|arg| {
+                            let arg = arg.to_string_lossy();
+                            arg == p_or_g.name || arg.ends_with(&format!("/{}", p_or_g.name))
+                        })
+                    })
+                    .map(|cmd| format!("{:?}", cmd))
+                    .collect(),
+            },
+        };
+        let mut planned_actions: Vec<PlannedAction> = actions
+            .to_add
+            .iter()
+            .map(|p_or_g| {
+                let reason = match &p_or_g.group {
+                    Some(group) => {
+                        let group = PackageOrGroup::new(group.clone(), p_or_g.manager.clone());
+                        format!("member of the group {} declared in {}", group.name, reference::join(reference.origins(&group)))
+                    }
+                    None => format!("declared in {}", reference::join(reference.origins(p_or_g))),
+                };
+                planned(p_or_g, Operation::Install, reason)
+            })
+            .chain(actions.to_delete.iter().map(|p_or_g| {
+                let exclusions = reference.exclusion_origins(&p_or_g.name);
+                let reason = if exclusions.is_empty() {
+                    "explicitly installed but not declared".to_string()
+                } else {
+                    format!("excluded in {}", exclusions.iter().map(|origin| origin.to_string()).collect::<Vec<String>>().join(", "))
+                };
+                planned(p_or_g, Operation::Remove, reason)
+            }))
+            .chain(actions.to_mark.iter().map(|p_or_g| {
+                let reason = match p_or_g.reason {
+                    InstallReason::Dependency => "explicitly installed, not declared but required by other packages".to_string(),
+                    InstallReason::Explicit => format!("installed as a dependency, declared in {}", reference::join(reference.origins(p_or_g))),
+                };
+                planned(p_or_g, Operation::Mark, reason)
+            }))
+            .chain(actions.drifted.iter().map(|(p_or_g, installed)| {
+                let declared = p_or_g.repository.as_deref().unwrap_or_default();
+                planned(p_or_g, Operation::Reinstall, format!("installed from {}, declared in repository {}", installed, declared))
+            }))
+            .chain(actions.unsatisfied.iter().map(|(p_or_g, installed)| {
+                let required = p_or_g.version.as_deref().unwrap_or_default();
+                let operation = if Constraint::parse(required).is_ok_and(|c| c.needs_downgrade(installed)) {
+                    Operation::Downgrade
+                } else {
+                    Operation::Upgrade
+                };
+                planned(p_or_g, operation, format!("installed {}, required {}", installed, required))
+            }))
+            .chain(actions.to_hold.iter().map(|p_or_g| planned(p_or_g, Operation::Hold, format!("held in {}", reference::join(reference.hold_origins(p_or_g))))))
+            .chain(actions.to_release.iter().map(|p_or_g| planned(p_or_g, Operation::Release, "held but no longer declared as held".to_string())))
+            .collect();
+        planned_actions.sort_by(|a, b| (a.operation, a.manager.to_string(), &a.name).cmp(&(b.operation, b.manager.to_string(), &b.name)));
+        Plan { actions: planned_actions }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+    use std::path::PathBuf;
+    use crate::engine::reference::Origin;
+    use super::*;
+
+    #[test]
+    fn plan_sorted_with_commands() {
+        // Given
+        let actions = Actions {
+            to_add: HashSet::from([
+                PackageOrGroup::new("vim".to_string(), PackageManager::PACMAN),
+                PackageOrGroup::new("org.gimp.GIMP".to_string(), PackageManager::FLATPAK).with_repository("flathub".to_string()),
+            ]),
+            to_delete: HashSet::from([PackageOrGroup::new("nano".to_string(), PackageManager::PACMAN)]),
+            ..Default::default()
+        };
+        let mut install = Command::new("pacman");
+        install.args(["-S", "vim"]);
+        let mut remove = Command::new("pacman");
+        remove.args(["-R", "nano"]);
+
+        let mut reference = Reference::default();
+        reference.insert(PackageOrGroup::new("vim".to_string(), PackageManager::PACMAN), Origin::new(PathBuf::from("target/console"), 2));
+
+        // When
+        let plan = Plan::new(&actions, &[install, remove], &[], &reference);
+
+        // Then
+        let names: Vec<&str> = plan.actions.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, vec!["org.gimp.GIMP", "vim", "nano"]);
+        assert!(plan.actions[0].commands.is_empty());
+        assert_eq!(plan.actions[1].commands, vec!["\"pacman\" \"-S\" \"vim\"".to_string()]);
+        assert_eq!(plan.actions[1].reason, "declared in target/console:2");
+        assert_eq!(plan.actions[2].operation, Operation::Remove);
+        assert_eq!(plan.actions[2].reason, "explicitly installed but not declared");
+    }
+
+    #[test]
+    fn plan_group_members() {
+        // Given
+        let actions = Actions {
+            to_add: HashSet::from([PackageOrGroup::new("gdm".to_string(), PackageManager::PACMAN).with_group("gnome".to_string())]),
+            ..Default::default()
+        };
+        let mut reference = Reference::default();
+        reference.insert(PackageOrGroup::new("gnome".to_string(), PackageManager::PACMAN), Origin::new(PathBuf::from("target/desktop"), 1));
+
+        // When
+        let plan = Plan::new(&actions, &[], &[], &reference);
+
+        // Then
+        assert_eq!(plan.actions[0].reason, "member of the group gnome declared in target/desktop:1");
+    }
+
+    #[test]
+    fn plan_version_constraints() {
+        // Given
+        let linux = PackageOrGroup::new("linux-lts".to_string(), PackageManager::PACMAN).with_version("=6.6.*".to_string());
+        let vim = PackageOrGroup::new("vim".to_string(), PackageManager::PACMAN)
+            .with_repository("extra".to_string())
+            .with_version(">=9.1".to_string());
+        let actions = Actions {
+            unsatisfied: HashMap::from([(linux, "6.9.7-1".to_string()), (vim, "9.0.0-1".to_string())]),
+            ..Default::default()
+        };
+        let mut install = Command::new("pacman");
+        install.args(["-S", "extra/vim"]);
+
+        // When
+        let plan = Plan::new(&actions, &[install], &[], &Reference::default());
+
+        // Then
+        assert_eq!(plan.actions[0].name, "vim");
+        assert_eq!(plan.actions[0].operation, Operation::Upgrade);
+        assert_eq!(plan.actions[0].commands, vec!["\"pacman\" \"-S\" \"extra/vim\"".to_string()]);
+        assert_eq!(plan.actions[1].operation, Operation::Downgrade);
+        assert_eq!(plan.actions[1].reason, "installed 6.9.7-1, required =6.6.*");
+    }
+
+    #[test]
+    fn plan_holds_with_hold_commands() {
+        // Given
+        let mesa = PackageOrGroup::new("mesa".to_string(), PackageManager::PACMAN);
+        let actions = Actions {
+            held: HashSet::from([mesa.clone()]),
+            to_hold: HashSet::from([mesa]),
+            to_release: HashSet::from([PackageOrGroup::new("nvidia-dkms".to_string(), PackageManager::PACMAN)]),
+            ..Default::default()
+        };
+        let mut hold = Command::new("sed");
+        hold.args(["-i", "-e", "/^\\[options\\]/a IgnorePkg = mesa", "/etc/pacman.conf"]);
+
+        // When
+        let plan = Plan::new(&actions, &[], &[hold], &Reference::default());
+
+        // Then
+        let expected = vec![r#""sed" "-i" "-e" "/^\\[options\\]/a IgnorePkg = mesa" "/etc/pacman.conf""#.to_string()];
+        assert_eq!(plan.actions[0].operation, Operation::Hold);
+        assert_eq!(plan.actions[0].commands, expected);
+        assert_eq!(plan.actions[1].operation, Operation::Release);
+        assert_eq!(plan.actions[1].commands, expected);
+    }
+
+    #[test]
+    fn plan_serialization() {
+        // Given
+        let actions = Actions {
+            to_add: HashSet::from([PackageOrGroup::new("vim".to_string(), PackageManager::PACMAN)]),
+            to_delete: HashSet::new(),
+            ..Default::default()
+        };
+
+        // When
+        let mut reference = Reference::default();
+        reference.insert(PackageOrGroup::new("vim".to_string(), PackageManager::PACMAN), Origin::new(PathBuf::from("target/console"), 2));
+        let json = serde_json::to_string(&Plan::new(&actions, &[], &[], &reference)).unwrap();
+        let toml = toml::to_string(&Plan::new(&actions, &[], &[], &reference)).unwrap();
+
+        // Then
+        assert_eq!(json, r#"{"actions":[{"name":"vim","manager":"pacman","operation":"install","reason":"declared in target/console:2","commands":[]}]}"#);
+        assert!(toml.starts_with("[[actions]]\nname = \"vim\"\nmanager = \"pacman\"\n"), "{}", toml);
+    }
+}
@@ -0,0 +1,198 @@
+/*
+ *  engine/reference.rs
+ *
+ *  Copyright (c) 2024-2024 Cédric ROYER <cedric dot royer at zaclys dot net>
+ *
+ *  This program is free software; you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation; either version 2 of the License, or
+ *  (at your option) any later version.
+ *
+ *  This program is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::Display;
+use std::path::PathBuf;
+use serde::Serialize;
+use crate::engine::compute_actions::PackageOrGroup;
+
+/// Place of a declaration in the configuration.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Serialize)]
+pub struct Origin {
+    pub path: PathBuf,
+    /// line number, starting at 1
+    pub line: usize,
+}
+
+/// Free-form information about a declaration, from the structured configuration files.
+#[derive(PartialEq, Eq, Debug, Default, Clone, Serialize)]
+pub struct Annotation {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    /// person or team responsible for the package
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+}
+
+/// Declared packages and groups, with every place declaring them.
+#[derive(PartialEq, Debug, Default)]
+pub struct Reference {
+    pub packages: HashMap<PackageOrGroup, Vec<Origin>>,
+    pub annotations: HashMap<PackageOrGroup, Annotation>,
+    /// packages and groups subtracted from the declared ones, wherever they are declared
+    pub excluded: HashMap<PackageOrGroup, Vec<Origin>>,
+    /// packages held back from upgrades, they are also declared
+    pub held: HashMap<PackageOrGroup, Vec<Origin>>,
+    /// configuration files read, included ones too
+    pub files: BTreeSet<PathBuf>,
+}
+
+impl Origin {
+    pub fn new(path: PathBuf, line: usize) -> Self {
+        Origin{path, line}
+    }
+}
+
+impl Reference {
+    /// Add a declaration, the first one of a package is kept along with all the origins.
+    /// A file included several times declares its packages once.
+    pub fn insert(&mut self, p_or_g: PackageOrGroup, origin: Origin) {
+        let origins = self.packages.entry(p_or_g).or_default();
+        if !origins.contains(&origin) {
+            origins.push(origin);
+        }
+    }
+
+    pub fn annotate(&mut self, p_or_g: PackageOrGroup, annotation: Annotation) {
+        self.annotations.insert(p_or_g, annotation);
+    }
+
+    pub fn annotation(&self, p_or_g: &PackageOrGroup) -> Option<&Annotation> {
+        self.annotations.get(p_or_g)
+    }
+
+    /// Exclude a package or a group, an exclusion wins over any declaration.
+    pub fn exclude(&mut self, p_or_g: PackageOrGroup, origin: Origin) {
+        let origins = self.excluded.entry(p_or_g).or_default();
+        if !origins.contains(&origin) {
+            origins.push(origin);
+        }
+    }
+
+    /// Declare a package and hold it back from upgrades.
+    pub fn hold(&mut self, p_or_g: PackageOrGroup, origin: Origin) {
+        let origins = self.held.entry(p_or_g.clone()).or_default();
+        if !origins.contains(&origin) {
+            origins.push(origin.clone());
+        }
+        self.insert(p_or_g, origin);
+    }
+
+    /// Held packages, without their origins.
+    pub fn held(&self) -> HashSet<PackageOrGroup> {
+        self.held.keys().cloned().collect()
+    }
+
+    /// Places holding the package, empty if it is not held.
+    pub fn hold_origins(&self, p_or_g: &PackageOrGroup) -> &[Origin] {
+        self.held.get(p_or_g).map(|origins| origins.as_slice()).unwrap_or_default()
+    }
+
+    /// Declared packages and groups which are not excluded, without their origins.
+    pub fn declared(&self) -> HashSet<PackageOrGroup> {
+        self.packages.keys().filter(|p_or_g| !self.excluded.contains_key(p_or_g)).cloned().collect()
+    }
+
+    /// Excluded packages and groups, without their origins.
+    pub fn excluded(&self) -> HashSet<PackageOrGroup> {
+        self.excluded.keys().cloned().collect()
+    }
+
+    /// Places excluding a package with this name, whatever its package manager.
+    pub fn exclusion_origins(&self, name: &str) -> Vec<&Origin> {
+        let mut origins: Vec<&Origin> = self.excluded.iter().filter(|(p_or_g, _)| p_or_g.name == name).flat_map(|(_, origins)| origins).collect();
+        origins.sort();
+        origins
+    }
+
+    /// Places declaring the package, empty if it is not declared.
+    pub fn origins(&self, p_or_g: &PackageOrGroup) -> &[Origin] {
+        self.packages.get(p_or_g).map(|origins| origins.as_slice()).unwrap_or_default()
+    }
+
+    /// Declarations with this name, whatever their package manager.
+    pub fn find(&self, name: &str) -> Vec<(&PackageOrGroup, &Vec<Origin>)> {
+        let mut found: Vec<(&PackageOrGroup, &Vec<Origin>)> = self.packages.iter().filter(|(p_or_g, _)| p_or_g.name == name).collect();
+        found.sort_by_key(|(p_or_g, _)| p_or_g.manager.to_string());
+        found
+    }
+
+    /// Packages declared more than once, sorted by name.
+    pub fn duplicates(&self) -> Vec<String> {
+        let mut duplicates: Vec<String> = self
+            .packages
+            .iter()
+            .filter(|(_, origins)| origins.len() > 1)
+            .map(|(p_or_g, origins)| format!("{} is declared {} times: {}", p_or_g.name, origins.len(), join(origins)))
+            .collect();
+        duplicates.sort();
+        duplicates
+    }
+}
+
+/// Origins separated by commas: `target/base:3, target/desktop:5`.
+pub fn join(origins: &[Origin]) -> String {
+    origins.iter().map(|origin| origin.to_string()).collect::<Vec<String>>().join(", ")
+}
+
+impl Display for Origin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.path.display(), self.line)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::compute_actions::PackageManager;
+
+    #[test]
+    fn duplicates_with_origins() {
+        // Given
+        let mut reference = Reference::default();
+        let vim = PackageOrGroup::new("vim".to_string(), PackageManager::PACMAN);
+        reference.insert(vim.clone(), Origin::new(PathBuf::from("target/console"), 2));
+        reference.insert(PackageOrGroup::new("base".to_string(), PackageManager::PACMAN), Origin::new(PathBuf::from("target/console"), 3));
+        reference.insert(vim.clone(), Origin::new(PathBuf::from("target/desktop"), 7));
+        reference.insert(vim.clone(), Origin::new(PathBuf::from("target/desktop"), 7));
+
+        // When
+        let duplicates = reference.duplicates();
+
+        // Then
+        assert_eq!(duplicates, vec!["vim is declared 2 times: target/console:2, target/desktop:7".to_string()]);
+        assert_eq!(reference.origins(&vim).len(), 2);
+        assert_eq!(reference.declared().len(), 2);
+    }
+
+    #[test]
+    fn exclusion_wins() {
+        // Given
+        let mut reference = Reference::default();
+        let epiphany = PackageOrGroup::new("epiphany".to_string(), PackageManager::PACMAN);
+        reference.insert(epiphany.clone(), Origin::new(PathBuf::from("shared/desktop"), 4));
+        reference.exclude(epiphany.clone(), Origin::new(PathBuf::from("hosts/laptop/desktop"), 1));
+
+        // When / Then
+        assert!(reference.declared().is_empty());
+        assert_eq!(reference.excluded(), HashSet::from([epiphany]));
+        assert_eq!(reference.exclusion_origins("epiphany"), vec![&Origin::new(PathBuf::from("hosts/laptop/desktop"), 1)]);
+    }
+}
@@ -0,0 +1,219 @@
+/*
+ *  engine/version.rs
+ *
+ *  Copyright (c) 2024-2024 Cédric ROYER <cedric dot royer at zaclys dot net>
+ *
+ *  This program is free software; you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation; either version 2 of the License, or
+ *  (at your option) any later version.
+ *
+ *  This program is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use std::cmp::Ordering;
+use std::fmt::Display;
+use glob::Pattern;
+
+/// Version required for a package: `>=2.0`, `<3`, `=6.6.*`...
+/// With `=`, the version is a glob pattern matched against the installed version.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Constraint {
+    pub operator: Operator,
+    pub version: String,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Operator {
+    Equal,
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+}
+
+impl Constraint {
+    /// Parse a constraint, the operator comes first: `>=2.0`.
+    pub fn parse(raw: &str) -> Result<Constraint, String> {
+        let operators = [
+            (">=", Operator::GreaterOrEqual),
+            ("<=", Operator::LessOrEqual),
+            (">", Operator::Greater),
+            ("<", Operator::Less),
+            ("=", Operator::Equal),
+        ];
+        let (operator, version) = operators
+            .iter()
+            .find_map(|(prefix, operator)| raw.strip_prefix(prefix).map(|version| (*operator, version)))
+            .ok_or(format!("version constraint without operator: {}", raw))?;
+        if version.is_empty() {
+            return Err(format!("version constraint without version: {}", raw));
+        }
+        if operator == Operator::Equal {
+            Pattern::new(version).map_err(|err| format!("invalid version pattern {}: {}", version, err))?;
+        }
+        Ok(Constraint{operator, version: version.to_string()})
+    }
+
+    /// Whether the installed version is newer than the required ones.
+    pub fn needs_downgrade(&self, installed: &str) -> bool {
+        !self.is_satisfied_by(installed) && vercmp(installed, &self.version) == Ordering::Greater
+    }
+
+    pub fn is_satisfied_by(&self, installed: &str) -> bool {
+        let ordering = vercmp(installed, &self.version);
+        match self.operator {
+            Operator::Equal => ordering == Ordering::Equal || Pattern::new(&self.version).is_ok_and(|p| p.matches(installed)),
+            Operator::Greater => ordering == Ordering::Greater,
+            Operator::GreaterOrEqual => ordering != Ordering::Less,
+            Operator::Less => ordering == Ordering::Less,
+            Operator::LessOrEqual => ordering != Ordering::Greater,
+        }
+    }
+}
+
+/// Split a name followed by a constraint: `foo>=2.0` is `foo` and `>=2.0`.
+pub fn split_constraint(raw: &str) -> (&str, Option<&str>) {
+    match raw.find(['<', '>', '=']) {
+        Some(index) => (&raw[..index], Some(&raw[index..])),
+        None => (raw, None),
+    }
+}
+
+/// Compare two versions like `vercmp` of pacman: `[epoch:]version[-release]`.
+/// The release is only compared when both versions have one.
+pub fn vercmp(a: &str, b: &str) -> Ordering {
+    if a == b {
+        return Ordering::Equal;
+    }
+    let (epoch_a, version_a, release_a) = parse_evr(a);
+    let (epoch_b, version_b, release_b) = parse_evr(b);
+    let release = match (release_a, release_b) {
+        (Some(release_a), Some(release_b)) => rpmvercmp(release_a, release_b),
+        _ => Ordering::Equal,
+    };
+    rpmvercmp(epoch_a, epoch_b).then(rpmvercmp(version_a, version_b)).then(release)
+}
+
+fn parse_evr(raw: &str) -> (&str, &str, Option<&str>) {
+    let (epoch, rest) = match raw.split_once(':') {
+        Some((epoch, rest)) if epoch.chars().all(|c| c.is_ascii_digit()) => (if epoch.is_empty() { "0" } else { epoch }, rest),
+        _ => ("0", raw),
+    };
+    match rest.rsplit_once('-') {
+        Some((version, release)) => (epoch, version, Some(release)),
+        None => (epoch, rest, None),
+    }
+}
+
+/// Compare version segments: numeric segments are newer than alphabetic ones,
+/// and a remaining alphabetic segment never beats an empty one (`1.0alpha` < `1.0`).
+fn rpmvercmp(a: &str, b: &str) -> Ordering {
+    if a == b {
+        return Ordering::Equal;
+    }
+    let (one, two) = (a.as_bytes(), b.as_bytes());
+    let (mut i, mut j) = (0, 0);
+    while i < one.len() && j < two.len() {
+        let (start_i, start_j) = (i, j);
+        while i < one.len() && !one[i].is_ascii_alphanumeric() {
+            i += 1;
+        }
+        while j < two.len() && !two[j].is_ascii_alphanumeric() {
+            j += 1;
+        }
+        if i >= one.len() || j >= two.len() {
+            break;
+        }
+        // different separators
+        if i - start_i != j - start_j {
+            return (i - start_i).cmp(&(j - start_j));
+        }
+        let is_num = one[i].is_ascii_digit();
+        let segment = |bytes: &[u8], mut end: usize| {
+            while end < bytes.len() && (if is_num { bytes[end].is_ascii_digit() } else { bytes[end].is_ascii_alphabetic() }) {
+                end += 1;
+            }
+            end
+        };
+        let (end_i, end_j) = (segment(one, i), segment(two, j));
+        if end_j == j {
+            return if is_num { Ordering::Greater } else { Ordering::Less };
+        }
+        let (mut segment_one, mut segment_two) = (&a[i..end_i], &b[j..end_j]);
+        if is_num {
+            segment_one = segment_one.trim_start_matches('0');
+            segment_two = segment_two.trim_start_matches('0');
+            let by_length = segment_one.len().cmp(&segment_two.len());
+            if by_length != Ordering::Equal {
+                return by_length;
+            }
+        }
+        let ordering = segment_one.cmp(segment_two);
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+        (i, j) = (end_i, end_j);
+    }
+    let (rest_one, rest_two) = (&one[i..], &two[j..]);
+    if rest_one.is_empty() && rest_two.is_empty() {
+        return Ordering::Equal;
+    }
+    if (rest_one.is_empty() && !rest_two[0].is_ascii_alphabetic()) || rest_one.first().is_some_and(|c| c.is_ascii_alphabetic()) {
+        Ordering::Less
+    } else {
+        Ordering::Greater
+    }
+}
+
+impl Display for Constraint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let operator = match self.operator {
+            Operator::Equal => "=",
+            Operator::Greater => ">",
+            Operator::GreaterOrEqual => ">=",
+            Operator::Less => "<",
+            Operator::LessOrEqual => "<=",
+        };
+        write!(f, "{}{}", operator, self.version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vercmp_like_pacman() {
+        assert_eq!(vercmp("1.0", "1.0"), Ordering::Equal);
+        assert_eq!(vercmp("1.0", "1.1"), Ordering::Less);
+        assert_eq!(vercmp("1.10", "1.9"), Ordering::Greater);
+        assert_eq!(vercmp("1.0alpha", "1.0"), Ordering::Less);
+        assert_eq!(vercmp("1.0a", "1.0.1"), Ordering::Less);
+        assert_eq!(vercmp("1.0-2", "1.0-10"), Ordering::Less);
+        assert_eq!(vercmp("1.0", "1.0-1"), Ordering::Equal);
+        assert_eq!(vercmp("1:1.0", "2.0"), Ordering::Greater);
+        assert_eq!(vercmp("6.6.30-1", "6.9.7.arch1-1"), Ordering::Less);
+        assert_eq!(vercmp("1.001", "1.1"), Ordering::Equal);
+    }
+
+    #[test]
+    fn constraints() {
+        assert!(Constraint::parse("=6.6.*").unwrap().is_satisfied_by("6.6.30-1"));
+        assert!(!Constraint::parse("=6.6.*").unwrap().is_satisfied_by("6.9.7.arch1-1"));
+        assert!(Constraint::parse(">=2.0").unwrap().is_satisfied_by("2.0-1"));
+        assert!(!Constraint::parse("<2.0").unwrap().is_satisfied_by("2.1-1"));
+        assert!(Constraint::parse("=1.0-1").unwrap().is_satisfied_by("1.0-1"));
+        assert!(Constraint::parse("=6.6.*").unwrap().needs_downgrade("6.9.7.arch1-1"));
+        assert!(!Constraint::parse(">=2.0").unwrap().needs_downgrade("1.0-1"));
+        assert!(Constraint::parse("2.0").is_err());
+        assert_eq!(split_constraint("linux-lts=6.6.*"), ("linux-lts", Some("=6.6.*")));
+        assert_eq!(split_constraint("vim"), ("vim", None));
+    }
+}
@@ -0,0 +1,386 @@
+/*
+ *  history.rs
+ *
+ *  Copyright (c) 2024-2024 Cédric ROYER <cedric dot royer at zaclys dot net>
+ *
+ *  This program is free software; you can redistribute it and/or modify
+ *  it under the terms of the GNU General Public License as published by
+ *  the Free Software Foundation; either version 2 of the License, or
+ *  (at your option) any later version.
+ *
+ *  This program is distributed in the hope that it will be useful,
+ *  but WITHOUT ANY WARRANTY; without even the implied warranty of
+ *  MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ *  GNU General Public License for more details.
+ *
+ *  You should have received a copy of the GNU General Public License
+ *  along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::backend::{self, BackendError};
+use crate::engine::compute_actions::{Actions, InstallReason, PackageManager, PackageOrGroup};
+use crate::engine::plan::Operation;
+use crate::engine::version::Constraint;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::env;
+use std::ffi::OsString;
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug)]
+pub enum HistoryError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    UnknownRun(u32),
+}
+
+pub type Result<T> = std::result::Result<T, HistoryError>;
+
+/// Journal of the applied runs, a `<id>.json` file per run.
+pub struct History {
+    dir: PathBuf,
+}
+
+/// An applied run.
+#[derive(PartialEq, Debug, Serialize, Deserialize)]
+pub struct Entry {
+    pub id: u32,
+    /// seconds since the Unix epoch
+    pub timestamp: u64,
+    /// sha256 of the configuration files, see `config_hash`
+    pub config_hash: String,
+    pub changes: Vec<Change>,
+    /// 0 when every command succeeded
+    pub exit_status: u8,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A change made to a package by a run.
+#[derive(PartialEq, Debug, Serialize, Deserialize)]
+pub struct Change {
+    pub name: String,
+    pub manager: PackageManager,
+    pub operation: Operation,
+    /// version installed before the run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_version: Option<String>,
+    /// install reason set by the run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<InstallReason>,
+}
+
+impl History {
+    pub fn new(dir: PathBuf) -> Self {
+        History{dir}
+    }
+
+    /// Every recorded run, oldest first. No directory means no run.
+    pub fn entries(&self) -> Result<Vec<Entry>> {
+        if !self.dir.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|extension| extension == "json") {
+                entries.push(serde_json::from_str::<Entry>(&fs::read_to_string(path)?)?);
+            }
+        }
+        entries.sort_by_key(|entry| entry.id);
+        Ok(entries)
+    }
+
+    pub fn get(&self, id: u32) -> Result<Entry> {
+        let path = self.dir.join(format!("{}.json", id));
+        if !path.is_file() {
+            return Err(HistoryError::UnknownRun(id));
+        }
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// Record a run with the next identifier, which is returned.
+    pub fn record(&self, mut entry: Entry) -> Result<u32> {
+        entry.id = self.entries()?.last().map(|last| last.id + 1).unwrap_or(1);
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.dir.join(format!("{}.json", entry.id)), serde_json::to_string_pretty(&entry)?)?;
+        Ok(entry.id)
+    }
+}
+
+impl Entry {
+    /// Run applying the actions, `removed_along` being the packages removed along with the deleted
+    /// ones and `versions` the versions installed before the run. The unsatisfied constraints are
+    /// only in the actions when they are enforced. The identifier is given when the run is recorded.
+    pub fn new(
+        config_hash: String,
+        actions: &Actions,
+        removed_along: &[PackageOrGroup],
+        versions: &HashMap<String, String>,
+        result: &backend::Result<()>,
+    ) -> Self {
+        let change = |p_or_g: &PackageOrGroup, operation: Operation, reason: Option<InstallReason>| Change {
+            name: p_or_g.name.clone(),
+            manager: p_or_g.manager.clone(),
+            operation,
+            previous_version: versions.get(&p_or_g.name).cloned(),
+            reason,
+        };
+        let mut changes: Vec<Change> = actions
+            .to_add
+            .iter()
+            .map(|p_or_g| change(p_or_g, Operation::Install, Some(p_or_g.reason)))
+            .chain(actions.to_delete.iter().map(|p_or_g| change(p_or_g, Operation::Remove, None)))
+            .chain(removed_along.iter().map(|p_or_g| change(p_or_g, Operation::Remove, Some(p_or_g.reason))))
+            .chain(actions.to_mark.iter().map(|p_or_g| change(p_or_g, Operation::Mark, Some(p_or_g.reason))))
+            .chain(actions.drifted.keys().map(|p_or_g| change(p_or_g, Operation::Reinstall, None)))
+            .chain(actions.unsatisfied.iter().map(|(p_or_g, installed)| {
+                let downgrade = p_or_g.version.as_ref().and_then(|v| Constraint::parse(v).ok()).is_some_and(|c| c.needs_downgrade(installed));
+                change(p_or_g, if downgrade { Operation::Downgrade } else { Operation::Upgrade }, None)
+            }))
+            .chain(actions.to_hold.iter().map(|p_or_g| change(p_or_g, Operation::Hold, None)))
+            .chain(actions.to_release.iter().map(|p_or_g| change(p_or_g, Operation::Release, None)))
+            .collect();
+        changes.sort_by(|a, b| (a.operation, a.manager.to_string(), &a.name).cmp(&(b.operation, b.manager.to_string(), &b.name)));
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default();
+        // the status of the failed command, when it exited with one
+        let exit_status = match result {
+            Ok(()) => 0,
+            Err(BackendError::CommandFailed(_, status)) => status.code().and_then(|code| u8::try_from(code).ok()).unwrap_or(1),
+            Err(_) => 1,
+        };
+        let error = result.as_ref().err().map(|err| err.to_string());
+        Entry{id: 0, timestamp, config_hash, changes, exit_status, error}
+    }
+
+    /// Actions undoing the run: installed packages are removed, removed or replaced packages are
+    /// installed again at their previous version and with their reason, marks and holds are reverted.
+    /// `held` is the current hold list.
+    pub fn inverse(&self, held: HashSet<PackageOrGroup>) -> Actions {
+        let mut actions = Actions{held, ..Default::default()};
+        for change in self.changes.iter() {
+            let p_or_g = PackageOrGroup::new(change.name.clone(), change.manager.clone());
+            let previous = || match &change.previous_version {
+                Some(version) => p_or_g.clone().with_version(format!("={}", version)),
+                None => p_or_g.clone(),
+            };
+            match change.operation {
+                Operation::Install => {
+                    actions.to_delete.insert(p_or_g);
+                }
+                Operation::Remove | Operation::Reinstall | Operation::Upgrade | Operation::Downgrade => {
+                    actions.to_add.insert(previous().with_reason(change.reason.unwrap_or_default()));
+                }
+                Operation::Mark => {
+                    let reason = match change.reason {
+                        Some(InstallReason::Dependency) => InstallReason::Explicit,
+                        _ => InstallReason::Dependency,
+                    };
+                    actions.to_mark.insert(p_or_g.with_reason(reason));
+                }
+                Operation::Hold => {
+                    actions.held.remove(&p_or_g);
+                    actions.to_release.insert(p_or_g);
+                }
+                Operation::Release => {
+                    actions.held.insert(p_or_g.clone());
+                    actions.to_hold.insert(p_or_g);
+                }
+            }
+        }
+        actions
+    }
+}
+
+/// `$XDG_STATE_HOME/pacsync/history`: pacsync runs as the user, only its commands use sudo.
+pub fn default_dir() -> PathBuf {
+    state_dir(env::var_os("XDG_STATE_HOME"), env::var_os("HOME")).join("pacsync").join("history")
+}
+
+/// The state directory defaults to `~/.local/state`, a relative `XDG_STATE_HOME` is ignored.
+fn state_dir(xdg_state_home: Option<OsString>, home: Option<OsString>) -> PathBuf {
+    match xdg_state_home.map(PathBuf::from).filter(|dir| dir.is_absolute()) {
+        Some(dir) => dir,
+        None => home.map(PathBuf::from).unwrap_or_default().join(".local").join("state"),
+    }
+}
+
+/// Hash of the configuration files, with their path, identifying the configuration of a run.
+pub fn config_hash(files: &BTreeSet<PathBuf>) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    for file in files {
+        hasher.update(file.to_string_lossy().as_bytes());
+        hasher.update([0]);
+        hasher.update(fs::read(file)?);
+    }
+    Ok(hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+impl Display for Entry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let date = humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_secs(self.timestamp));
+        let status = if self.exit_status == 0 { "success" } else { "failed" };
+        let mut counts: Vec<(Operation, usize)> = Vec::new();
+        for change in self.changes.iter() {
+            match counts.iter_mut().find(|(operation, _)| *operation == change.operation) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((change.operation, 1)),
+            }
+        }
+        let summary: Vec<String> = counts.iter().map(|(operation, count)| format!("{} {}", count, operation)).collect();
+        write!(f, "{}\t{}\t{}\t{}\t{}", self.id, date, status, &self.config_hash[..self.config_hash.len().min(12)], summary.join(", "))
+    }
+}
+
+impl From<io::Error> for HistoryError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for HistoryError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Json(err)
+    }
+}
+
+impl Display for HistoryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HistoryError::Io(err) => write!(f, "cannot access the history: {err}"),
+            HistoryError::Json(err) => write!(f, "invalid history entry: {err}"),
+            HistoryError::UnknownRun(id) => write!(f, "no run {id} in the history"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+    use super::*;
+
+    fn given_entry() -> Entry {
+        let actions = Actions {
+            to_add: HashSet::from([PackageOrGroup::new("vim".to_string(), PackageManager::PACMAN)]),
+            to_delete: HashSet::from([PackageOrGroup::new("nano".to_string(), PackageManager::PACMAN)]),
+            to_mark: HashSet::from([PackageOrGroup::new("glibc".to_string(), PackageManager::PACMAN).with_reason(InstallReason::Dependency)]),
+            to_hold: HashSet::from([PackageOrGroup::new("linux-lts".to_string(), PackageManager::PACMAN)]),
+            ..Default::default()
+        };
+        let removed_along = [PackageOrGroup::new("libunused".to_string(), PackageManager::PACMAN).with_reason(InstallReason::Dependency)];
+        let versions = HashMap::from([
+            ("nano".to_string(), "8.0-1".to_string()),
+            ("glibc".to_string(), "2.39-1".to_string()),
+            ("libunused".to_string(), "1.0-1".to_string()),
+        ]);
+        Entry::new("0123456789abcdef".to_string(), &actions, &removed_along, &versions, &Ok(()))
+    }
+
+    #[test]
+    fn entry_from_actions() {
+        // When
+        let entry = given_entry();
+
+        // Then
+        let operations: Vec<(&str, Operation)> = entry.changes.iter().map(|c| (c.name.as_str(), c.operation)).collect();
+        assert_eq!(operations, vec![
+            ("vim", Operation::Install),
+            ("libunused", Operation::Remove),
+            ("nano", Operation::Remove),
+            ("glibc", Operation::Mark),
+            ("linux-lts", Operation::Hold),
+        ]);
+        assert_eq!(entry.changes[1].reason, Some(InstallReason::Dependency));
+        assert_eq!(entry.changes[2].previous_version, Some("8.0-1".to_string()));
+        assert_eq!(entry.exit_status, 0);
+    }
+
+    #[test]
+    fn exit_status_of_a_failed_run() {
+        // Given
+        let failed = Err(BackendError::CommandFailed("\"pacman\" failed".to_string(), ExitStatus::from_raw(2 << 8)));
+        let killed = Err(BackendError::CommandFailed("\"pacman\" failed".to_string(), ExitStatus::from_raw(9)));
+
+        // When
+        let failed = Entry::new(String::new(), &Actions::default(), &[], &HashMap::new(), &failed);
+        let killed = Entry::new(String::new(), &Actions::default(), &[], &HashMap::new(), &killed);
+
+        // Then
+        assert_eq!(failed.exit_status, 2);
+        assert_eq!(failed.error, Some("\"pacman\" failed with status exit status: 2".to_string()));
+        assert_eq!(killed.exit_status, 1);
+    }
+
+    #[test]
+    fn inverse_of_a_run() {
+        // Given
+        let entry = given_entry();
+        let linux = PackageOrGroup::new("linux-lts".to_string(), PackageManager::PACMAN);
+
+        // When
+        let actions = entry.inverse(HashSet::from([linux.clone()]));
+
+        // Then
+        assert_eq!(actions.to_delete, HashSet::from([PackageOrGroup::new("vim".to_string(), PackageManager::PACMAN)]));
+        let nano = actions.to_add.iter().find(|p| p.name == "nano").unwrap();
+        assert_eq!((nano.version.as_deref(), nano.reason), (Some("=8.0-1"), InstallReason::Explicit));
+        let libunused = actions.to_add.iter().find(|p| p.name == "libunused").unwrap();
+        assert_eq!((libunused.version.as_deref(), libunused.reason), (Some("=1.0-1"), InstallReason::Dependency));
+        assert_eq!(actions.to_mark.iter().next().unwrap().reason, InstallReason::Explicit);
+        assert_eq!(actions.to_release, HashSet::from([linux]));
+        assert!(actions.held.is_empty());
+    }
+
+    #[test]
+    fn record_and_list() {
+        // Given
+        let dir = std::env::temp_dir().join(format!("pacsync-history-{}", std::process::id()));
+        let history = History::new(dir.clone());
+
+        // When
+        let first = history.record(given_entry()).unwrap();
+        let second = history.record(given_entry()).unwrap();
+        let entries = history.entries().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        // Then
+        assert_eq!((first, second), (1, 2));
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].changes, given_entry().changes);
+        assert!(entries[0].to_string().ends_with("\tsuccess\t0123456789ab\t1 install, 2 remove, 1 mark, 1 hold"), "{}", entries[0]);
+        assert!(matches!(history.get(3), Err(HistoryError::UnknownRun(3))));
+    }
+
+    #[test]
+    fn state_directory() {
+        // When
+        let xdg = state_dir(Some(OsString::from("/home/alice/.state")), Some(OsString::from("/home/alice")));
+        let relative = state_dir(Some(OsString::from("state")), Some(OsString::from("/home/alice")));
+        let home = state_dir(None, Some(OsString::from("/home/alice")));
+
+        // Then
+        assert_eq!(xdg, PathBuf::from("/home/alice/.state"));
+        assert_eq!(relative, PathBuf::from("/home/alice/.local/state"));
+        assert_eq!(home, PathBuf::from("/home/alice/.local/state"));
+    }
+
+    #[test]
+    fn hash_of_configuration() {
+        // Given
+        let files = BTreeSet::from([PathBuf::from("tests/data/hold/kernel")]);
+
+        // When
+        let hash = config_hash(&files).unwrap();
+
+        // Then
+        assert_eq!(hash.len(), 64);
+        assert_eq!(hash, config_hash(&files).unwrap());
+        assert_ne!(hash, config_hash(&BTreeSet::new()).unwrap());
+    }
+}
@@ -17,38 +17,21 @@
  *  along with this program.  If not, see <http://www.gnu.org/licenses/>.
  */
 
-use crate::configuration::reader;
-use crate::engine::compute_actions;
-use std::path::Path;
-use std::io;
+use clap::Parser;
+use std::process::ExitCode;
 
+pub mod backend;
+pub mod cli;
 pub mod configuration;
 pub mod engine;
-pub mod pacman;
+pub mod history;
 
-fn main() {
-    let reference = reader::read(Path::new("/etc/pacsync.d/target/")).expect("Cannot read configuration.");
-    let current = pacman::get_explicit_installed_packages().expect("Cannot query pacman.");
-    let actions = compute_actions::compute_actions(reference, current);
-    println!("# actions to be done\n{}", actions);
-    if actions.is_empty() {
-        return;
+fn main() -> ExitCode {
+    match cli::run(cli::Cli::parse()) {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::FAILURE
+        }
     }
-    println!("apply/print/no abort [y/p/n] ?");
-    let answer = get_answer().unwrap();
-    if answer == "y\n" {
-        pacman::apply_actions(&actions).expect("Cannot apply actions.");
-    } else if answer == "p\n" {
-        pacman::print_actions(&actions);
-    } else {
-        println!("Abort")
-    }
-}
-
-fn get_answer() -> io::Result<String> {
-    let mut buffer = String::new();
-
-    io::stdin().read_line(&mut buffer)?;
-
-    Ok(buffer)
 }